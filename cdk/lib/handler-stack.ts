@@ -1,15 +1,22 @@
 import { join } from 'path'
 import { RustFunction } from 'cargo-lambda-cdk'
 import { EndpointType, LambdaRestApi } from 'aws-cdk-lib/aws-apigateway'
-import { Duration, Stack, StackProps } from "aws-cdk-lib"
+import { ArnFormat, Duration, RemovalPolicy, Stack, StackProps } from "aws-cdk-lib"
 import { Construct } from "constructs"
 import { Effect, PolicyStatement } from 'aws-cdk-lib/aws-iam'
 import { Queue } from 'aws-cdk-lib/aws-sqs'
 import { SqsEventSource } from 'aws-cdk-lib/aws-lambda-event-sources'
 import { Rule, Schedule } from 'aws-cdk-lib/aws-events'
-import { LambdaFunction } from 'aws-cdk-lib/aws-events-targets'
+import { SfnStateMachine } from 'aws-cdk-lib/aws-events-targets'
+import { Choice, Condition, DefinitionBody, StateMachine, Succeed, Wait, WaitTime } from 'aws-cdk-lib/aws-stepfunctions'
+import { LambdaInvoke } from 'aws-cdk-lib/aws-stepfunctions-tasks'
+import { AttributeType, BillingMode, Table } from 'aws-cdk-lib/aws-dynamodb'
+import { ParamsAndSecretsLayerVersion, ParamsAndSecretsVersions } from 'aws-cdk-lib/aws-lambda'
 import { namePrefix } from '../bin/cdk'
 
+// attempts at a message before it goes to the dead-letter queue, passed to the sqs handler
+// so that it tells the asker about the failure on the last one.
+const MAX_RECEIVE_COUNT = 3
 
 
 export class HandlerStack extends Stack {
@@ -20,28 +27,108 @@ export class HandlerStack extends Stack {
     private chatModelId = this.context["CHAT_MODEL_ID"]
     private botToken = this.context["BOT_OAUTH_TOKEN"]
     private knowledgeBaseId: string = this.context["KNOWLEDGE_BASE_ID"]
+    private streamingEnabled: string = this.context["STREAMING_ENABLED"] ?? "false"
+    // Secrets Manager secret name / ARN, or Parameter Store parameter name (starting with `/`) / ARN
+    private slackSigningSecretId: string | undefined = this.context["SLACK_SIGNING_SECRET_ID"]
+    private botTokenId: string | undefined = this.context["BOT_OAUTH_TOKEN_ID"]
+    // buckets of S3 data sources, linked from the answers with presigned URLs
+    private s3SourceBuckets: string[] = this.context["S3_SOURCE_BUCKETS"] ?? []
+    private presignedUrlExpirySeconds: string | undefined = this.context["PRESIGNED_URL_EXPIRY_SECONDS"]
+    // Slack channel the sync summaries are posted to
+    private opsChannelId: string | undefined = this.context["OPS_CHANNEL_ID"]
+    // `skip`, `wait` or `restart` a sync when a data source is already being synced
+    private syncConflictPolicy: string | undefined = this.context["SYNC_CONFLICT_POLICY"]
+    // the data sources the scheduled sync syncs, by ID or name, or as patterns such as `eng-*`
+    private syncDataSources: string[] = this.context["SYNC_DATA_SOURCES"] ?? []
+    private syncInclude: string[] = this.context["SYNC_INCLUDE"] ?? []
+    private syncExclude: string[] = this.context["SYNC_EXCLUDE"] ?? []
+    // data source IDs or names (`*` for the others) to `hourly`, `daily`, `weekdays` or `weekly`
+    private syncFrequencies: { [dataSource: string]: string } | undefined = this.context["SYNC_FREQUENCIES"]
+    // Slack user IDs and user group IDs allowed to run `admin sync`
+    private adminUserIds: string[] = this.context["ADMIN_USER_IDS"] ?? []
+    private adminUserGroupIds: string[] = this.context["ADMIN_USER_GROUP_IDS"] ?? []
+    // Confluence space keys (`*` for the others) to the data source their page changes are synced to
+    private confluenceDataSources: { [spaceKey: string]: string } = this.context["CONFLUENCE_DATA_SOURCES"] ?? {}
+    private confluenceWebhookSecret: string | undefined = this.context["CONFLUENCE_WEBHOOK_SECRET"]
+    private confluenceWebhookSecretId: string | undefined = this.context["CONFLUENCE_WEBHOOK_SECRET_ID"]
+    private confluenceDebounceSeconds: string | undefined = this.context["CONFLUENCE_DEBOUNCE_SECONDS"]
+    // to fetch the changed pages of custom data sources
+    private confluenceBaseUrl: string | undefined = this.context["CONFLUENCE_BASE_URL"]
+    private confluenceEmail: string | undefined = this.context["CONFLUENCE_EMAIL"]
+    private confluenceApiToken: string | undefined = this.context["CONFLUENCE_API_TOKEN"]
+    private confluenceApiTokenId: string | undefined = this.context["CONFLUENCE_API_TOKEN_ID"]
 
 
     constructor(scope: Construct, id: string, props: StackProps) {
         super(scope, id, props)
 
+        // messages that kept failing with retryable errors (throttling, timeouts, Slack 5xx)
+        const deadLetterQueue = new Queue(this, `${namePrefix}SlackEventDeadLetterQueue.fifo`, {
+            retentionPeriod: Duration.days(14),
+            fifo: true,
+        })
+
         const queue = new Queue(this, `${namePrefix}SlackEventQueue.fifo`, {
             visibilityTimeout: Duration.minutes(10),
             fifo: true,
+            deadLetterQueue: {
+                queue: deadLetterQueue,
+                maxReceiveCount: MAX_RECEIVE_COUNT,
+            },
         })
 
+        // Bedrock session id per slack thread
+        const sessionTable = new Table(this, `${namePrefix}SessionTable`, {
+            partitionKey: { name: 'conversation_key', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            timeToLiveAttribute: 'expires_at',
+            removalPolicy: RemovalPolicy.DESTROY,
+        })
 
-        // apigateway lambda
+        // answers and their ratings
+        const feedbackTable = new Table(this, `${namePrefix}FeedbackTable`, {
+            partitionKey: { name: 'answer_id', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            removalPolicy: RemovalPolicy.RETAIN,
+        })
+
+        // slack events and slash commands already received / answered
+        const idempotencyTable = new Table(this, `${namePrefix}IdempotencyTable`, {
+            partitionKey: { name: 'idempotency_key', type: AttributeType.STRING },
+            billingMode: BillingMode.PAY_PER_REQUEST,
+            timeToLiveAttribute: 'expires_at',
+            removalPolicy: RemovalPolicy.DESTROY,
+        })
+
+        // apigateway lambda, also receiving the Confluence webhooks when some spaces are synced on change.
+        const confluenceWebhooksEnabled = Object.keys(this.confluenceDataSources).length > 0
+        const apigatewaySecretIds = [...this.slackSecretIds(), ...(confluenceWebhooksEnabled ? [this.confluenceWebhookSecretId] : [])]
         const apigatewayLambda = new RustFunction(this, `${namePrefix}APIGatewayLambda`, {
             manifestPath: join(__dirname, '..', '..', 'lambdas/receive_handler/Cargo.toml'),
             runtime: "provided.al2023",
             environment: {
-                "SLACK_SIGNING_SECRET": this.slackSigningSecret,
+                ...this.secretEnvironment(),
                 "QUEUE_URL": queue.queueUrl,
-            }
+                "FEEDBACK_TABLE_NAME": feedbackTable.tableName,
+                "IDEMPOTENCY_TABLE_NAME": idempotencyTable.tableName,
+                ...(confluenceWebhooksEnabled ? this.confluenceWebhookEnvironment() : {}),
+            },
+            paramsAndSecrets: this.paramsAndSecretsLayer(apigatewaySecretIds),
         })
 
         queue.grantSendMessages(apigatewayLambda)
+        feedbackTable.grantReadWriteData(apigatewayLambda)
+        idempotencyTable.grantReadWriteData(apigatewayLambda)
+        this.grantSecretsRead(apigatewayLambda, apigatewaySecretIds)
+
+        // to know whether the data source of a space is a custom one.
+        if (confluenceWebhooksEnabled) {
+            apigatewayLambda.addToRolePolicy(new PolicyStatement({
+                effect: Effect.ALLOW,
+                actions: ['bedrock:ListDataSources', 'bedrock:GetDataSource'],
+                resources: ['*'],
+            }))
+        }
 
         const restApi = new LambdaRestApi(this, `${namePrefix}APIGateway`, {
             handler: apigatewayLambda,
@@ -52,19 +139,33 @@ export class HandlerStack extends Stack {
             manifestPath: join(__dirname, '..', '..', 'lambdas/sqs_handler/Cargo.toml'),
             runtime: "provided.al2023",
             environment: {
-                "SLACK_SIGNING_SECRET": this.slackSigningSecret,
+                ...this.secretEnvironment(),
                 "QUEUE_ARN": queue.queueArn,
+                "MAX_RECEIVE_COUNT": String(MAX_RECEIVE_COUNT),
                 "CHAT_MODEL_ID": this.chatModelId,
                 "KNOWLEDGE_BASE_ID": this.knowledgeBaseId,
-                "BOT_OAUTH_TOKEN": this.botToken
+                "SESSION_TABLE_NAME": sessionTable.tableName,
+                "FEEDBACK_TABLE_NAME": feedbackTable.tableName,
+                "IDEMPOTENCY_TABLE_NAME": idempotencyTable.tableName,
+                "STREAMING_ENABLED": this.streamingEnabled,
+                ...(this.presignedUrlExpirySeconds ? { "PRESIGNED_URL_EXPIRY_SECONDS": this.presignedUrlExpirySeconds } : {}),
+                ...this.retrievalEnvironment(),
+                ...(this.adminUserIds.length > 0 ? { "ADMIN_USER_IDS": this.adminUserIds.join(",") } : {}),
+                ...(this.adminUserGroupIds.length > 0 ? { "ADMIN_USER_GROUP_IDS": this.adminUserGroupIds.join(",") } : {}),
             },
+            paramsAndSecrets: this.paramsAndSecretsLayer(),
             timeout: Duration.minutes(5)
         })
 
         queue.grantConsumeMessages(sqsLambda)
+        sessionTable.grantReadWriteData(sqsLambda)
+        feedbackTable.grantReadWriteData(sqsLambda)
+        idempotencyTable.grantReadWriteData(sqsLambda)
+        this.grantSecretsRead(sqsLambda)
         sqsLambda.addEventSource(
             new SqsEventSource(queue, {
-                batchSize: 1,
+                batchSize: 5,
+                reportBatchItemFailures: true,
             })
         )
 
@@ -76,14 +177,39 @@ export class HandlerStack extends Stack {
             resources: ['*'],
         }))
 
+        // presigned URLs are signed with the lambda's role.
+        if (this.s3SourceBuckets.length > 0) {
+            sqsLambda.addToRolePolicy(new PolicyStatement({
+                effect: Effect.ALLOW,
+                actions: ['s3:GetObject'],
+                resources: this.s3SourceBuckets.map((bucket) => `arn:${this.partition}:s3:::${bucket}/*`),
+            }))
+        }
+
+        // the sync lambda posts to Slack for the ops channel and the syncs started with `admin sync`.
+        const adminSyncEnabled = this.adminUserIds.length > 0 || this.adminUserGroupIds.length > 0
+        const dailyLambdaPostsToSlack = this.opsChannelId || adminSyncEnabled
+        // and fetches the changed pages of the custom data sources synced by the Confluence webhooks.
+        const dailyLambdaFetchesPages = confluenceWebhooksEnabled && this.confluenceBaseUrl
+        const dailySecretIds = [
+            ...(dailyLambdaPostsToSlack ? this.slackSecretIds() : []),
+            ...(dailyLambdaFetchesPages ? [this.confluenceApiTokenId] : []),
+        ]
         const dailyLambda = new RustFunction(this, `${namePrefix}DailyDataSyncLambda`, {
             manifestPath: join(__dirname, '..', '..', 'lambdas/daily_data_sync_handler/Cargo.toml'),
             runtime: "provided.al2023",
             environment: {
                 "KNOWLEDGE_BASE_ID": this.knowledgeBaseId,
+                ...(dailyLambdaPostsToSlack ? this.secretEnvironment() : {}),
+                ...(this.opsChannelId ? { "OPS_CHANNEL_ID": this.opsChannelId } : {}),
+                ...(this.syncConflictPolicy ? { "SYNC_CONFLICT_POLICY": this.syncConflictPolicy } : {}),
+                ...this.syncSelectionEnvironment(),
+                ...(dailyLambdaFetchesPages ? this.confluencePagesEnvironment() : {}),
             },
+            paramsAndSecrets: this.paramsAndSecretsLayer(dailySecretIds),
             timeout: Duration.minutes(5)
         });
+        this.grantSecretsRead(dailyLambda, dailySecretIds)
 
         dailyLambda.addToRolePolicy(new PolicyStatement({
             effect: Effect.ALLOW,
@@ -93,17 +219,191 @@ export class HandlerStack extends Stack {
             resources: ['*'],
         }))
 
-        // 00:00 UTC on Weekdays
+        // start the ingestion jobs, then check on them every few minutes until they finish.
+        // the lambda returns the jobs and whether they are `finished`, and is given them back to check.
+        const startDataSync = new LambdaInvoke(this, `${namePrefix}StartDataSync`, {
+            lambdaFunction: dailyLambda,
+            payloadResponseOnly: true,
+        })
+        const checkDataSync = new LambdaInvoke(this, `${namePrefix}CheckDataSync`, {
+            lambdaFunction: dailyLambda,
+            payloadResponseOnly: true,
+        })
+        const waitForDataSync = new Wait(this, `${namePrefix}WaitForDataSync`, {
+            time: WaitTime.duration(Duration.minutes(2)),
+        })
+        const dataSyncFinished = new Choice(this, `${namePrefix}DataSyncFinished`)
+            .when(Condition.booleanEquals('$.finished', true), new Succeed(this, `${namePrefix}DataSyncSucceeded`))
+            .otherwise(waitForDataSync)
+        waitForDataSync.next(checkDataSync)
+        checkDataSync.next(dataSyncFinished)
+
+        // the syncs of the Confluence webhooks wait for the end of their debounce window, given as `start_at`.
+        const waitForStart = new Wait(this, `${namePrefix}WaitForDataSyncStart`, {
+            time: WaitTime.timestampPath('$.start_at'),
+        })
+        waitForStart.next(startDataSync)
+        const dataSyncStart = new Choice(this, `${namePrefix}DataSyncStart`)
+            .when(Condition.isPresent('$.start_at'), waitForStart)
+            .otherwise(startDataSync)
+        startDataSync.next(dataSyncFinished)
+
+        const dataSyncStateMachine = new StateMachine(this, `${namePrefix}DataSyncStateMachine`, {
+            definitionBody: DefinitionBody.fromChainable(dataSyncStart),
+            timeout: Duration.hours(12),
+        })
+
+        // `admin sync` starts the same state machine from the sqs lambda.
+        if (adminSyncEnabled) {
+            sqsLambda.addEnvironment("SYNC_STATE_MACHINE_ARN", dataSyncStateMachine.stateMachineArn)
+            dataSyncStateMachine.grantStartExecution(sqsLambda)
+        }
+
+        // and the Confluence webhooks from the apigateway lambda, at `/confluence`.
+        if (confluenceWebhooksEnabled) {
+            apigatewayLambda.addEnvironment("SYNC_STATE_MACHINE_ARN", dataSyncStateMachine.stateMachineArn)
+            dataSyncStateMachine.grantStartExecution(apigatewayLambda)
+        }
+
+        // 00:00 UTC on Weekdays, unless some data sources are synced more often.
+        // the lambda only syncs the data sources due at the time of the event.
+        const frequencies = Object.values(this.syncFrequencies ?? {})
+        const schedule = frequencies.includes("hourly") ? Schedule.cron({ minute: '0' })
+            : frequencies.length > 0 ? Schedule.cron({ minute: '0', hour: '0' })
+            : Schedule.cron({ minute: '0', hour: '0', weekDay: 'MON-FRI' })
         const dailyRule = new Rule(this, `${namePrefix}DailyDataSyncRule`, {
-            schedule: Schedule.cron({
-                minute: '0',
-                hour: '0',
-                weekDay: 'MON-FRI',
-            }),
-            targets: [new LambdaFunction(dailyLambda, {
+            schedule,
+            targets: [new SfnStateMachine(dataSyncStateMachine, {
                 retryAttempts: 0
             })]
         })
 
     }
+
+    // read the slack secrets from Secrets Manager / Parameter Store when their ids are given,
+    // fall back to the plain values otherwise.
+    private secretEnvironment(): { [key: string]: string } {
+        const environment: { [key: string]: string } = {}
+        if (this.slackSigningSecretId) {
+            environment["SLACK_SIGNING_SECRET_ID"] = this.slackSigningSecretId
+        } else {
+            environment["SLACK_SIGNING_SECRET"] = this.slackSigningSecret
+        }
+        if (this.botTokenId) {
+            environment["BOT_OAUTH_TOKEN_ID"] = this.botTokenId
+        } else {
+            environment["BOT_OAUTH_TOKEN"] = this.botToken
+        }
+        return environment
+    }
+
+    // only the retrieval settings that are set, the others are left to Bedrock.
+    private retrievalEnvironment(): { [key: string]: string } {
+        const environment: { [key: string]: string } = {}
+        for (const key of ["RETRIEVAL_NUMBER_OF_RESULTS", "RETRIEVAL_SEARCH_TYPE", "MODEL_TEMPERATURE", "MODEL_MAX_TOKENS", "MODEL_TOP_P"]) {
+            if (this.context[key] !== undefined) {
+                environment[key] = `${this.context[key]}`
+            }
+        }
+        // channel IDs to settings, as an object or a JSON string
+        const channelSettings = this.context["CHANNEL_RETRIEVAL_SETTINGS"]
+        if (channelSettings) {
+            environment["CHANNEL_RETRIEVAL_SETTINGS"] = typeof channelSettings === "string" ? channelSettings : JSON.stringify(channelSettings)
+        }
+        return environment
+    }
+
+    // which data sources the scheduled sync syncs and how often, all of them on weekdays if unset.
+    private syncSelectionEnvironment(): { [key: string]: string } {
+        const environment: { [key: string]: string } = {}
+        if (this.syncDataSources.length > 0) {
+            environment["SYNC_DATA_SOURCES"] = this.syncDataSources.join(",")
+        }
+        if (this.syncInclude.length > 0) {
+            environment["SYNC_INCLUDE"] = this.syncInclude.join(",")
+        }
+        if (this.syncExclude.length > 0) {
+            environment["SYNC_EXCLUDE"] = this.syncExclude.join(",")
+        }
+        if (this.syncFrequencies) {
+            environment["SYNC_FREQUENCIES"] = JSON.stringify(this.syncFrequencies)
+        }
+        return environment
+    }
+
+    // the secret the webhooks are signed with, where the pages of each space are synced to and how long bursts of changes are debounced.
+    private confluenceWebhookEnvironment(): { [key: string]: string } {
+        const environment: { [key: string]: string } = {
+            "KNOWLEDGE_BASE_ID": this.knowledgeBaseId,
+            "CONFLUENCE_DATA_SOURCES": JSON.stringify(this.confluenceDataSources),
+        }
+        if (this.confluenceWebhookSecretId) {
+            environment["CONFLUENCE_WEBHOOK_SECRET_ID"] = this.confluenceWebhookSecretId
+        } else if (this.confluenceWebhookSecret) {
+            environment["CONFLUENCE_WEBHOOK_SECRET"] = this.confluenceWebhookSecret
+        }
+        if (this.confluenceDebounceSeconds !== undefined) {
+            environment["CONFLUENCE_DEBOUNCE_SECONDS"] = `${this.confluenceDebounceSeconds}`
+        }
+        return environment
+    }
+
+    // the site and the account the changed pages are fetched with.
+    private confluencePagesEnvironment(): { [key: string]: string } {
+        const environment: { [key: string]: string } = {
+            "CONFLUENCE_BASE_URL": this.confluenceBaseUrl ?? "",
+            "CONFLUENCE_EMAIL": this.confluenceEmail ?? "",
+        }
+        if (this.confluenceApiTokenId) {
+            environment["CONFLUENCE_API_TOKEN_ID"] = this.confluenceApiTokenId
+        } else if (this.confluenceApiToken) {
+            environment["CONFLUENCE_API_TOKEN"] = this.confluenceApiToken
+        }
+        return environment
+    }
+
+    private slackSecretIds(): (string | undefined)[] {
+        return [this.slackSigningSecretId, this.botTokenId]
+    }
+
+    // the extension the lambdas fetch the secrets through.
+    private paramsAndSecretsLayer(ids: (string | undefined)[] = this.slackSecretIds()): ParamsAndSecretsLayerVersion | undefined {
+        if (!ids.some((id) => id)) {
+            return undefined
+        }
+        return ParamsAndSecretsLayerVersion.fromVersion(ParamsAndSecretsVersions.V1_0_103)
+    }
+
+    private grantSecretsRead(lambda: RustFunction, ids: (string | undefined)[] = this.slackSecretIds()) {
+        for (const id of ids) {
+            if (!id) {
+                continue
+            }
+            // `arn:<partition>:ssm:...`, in any partition (aws-cn, aws-us-gov, ...)
+            const isParameter = id.startsWith('/') || (id.startsWith('arn:') && id.split(':')[2] === 'ssm')
+            if (isParameter) {
+                lambda.addToRolePolicy(new PolicyStatement({
+                    effect: Effect.ALLOW,
+                    actions: ['ssm:GetParameter'],
+                    resources: [id.startsWith('arn:') ? id : this.formatArn({
+                        service: 'ssm',
+                        resource: 'parameter',
+                        resourceName: id.substring(1),
+                    })],
+                }))
+            } else {
+                lambda.addToRolePolicy(new PolicyStatement({
+                    effect: Effect.ALLOW,
+                    actions: ['secretsmanager:GetSecretValue'],
+                    // secret ARNs end with a random suffix.
+                    resources: [id.startsWith('arn:') ? id : this.formatArn({
+                        service: 'secretsmanager',
+                        resource: 'secret',
+                        resourceName: `${id}-??????`,
+                        arnFormat: ArnFormat.COLON_RESOURCE_NAME,
+                    })],
+                }))
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,41 +1,389 @@
-use aws_lambda_events::eventbridge::EventBridgeEvent;
 use lambda_runtime::{
     service_fn,
     tracing::{self},
     Error, LambdaEvent,
 };
-use lib::service::CommonService;
-use serde_json::{json, Value};
+use lib::{
+    env_keys::{BOT_OAUTH_TOKEN, KNOWLEDGE_BASE_ID},
+    service::{
+        bedrock_service::data_sync::{DataSyncRun, IngestionJobReport, SyncRequest},
+        CommonService,
+    },
+};
+use serde::Deserialize;
+
+/// Any event but a [`DataSyncRun`] starts a sync, ex: the scheduled EventBridge event
+/// syncs the data sources due at that time, and a [`SyncRequest`] from `admin sync` or a Confluence webhook the ones asked for.
+/// The state machine then passes the returned run back until it is `finished`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SyncEvent {
+    Check(DataSyncRun),
+    Start(Box<SyncRequest>),
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
 
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::v2025_08_07()).await;
-    let service = CommonService::new(&config);
-    let service_function = service_fn(|event| async { eventbridge_handler(event, &service).await });
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::v2026_01_12()).await;
+    let service = CommonService::new(&config, &[KNOWLEDGE_BASE_ID]).await?;
+    if service.config.ops_channel_id.is_some() {
+        service.config.require(&[BOT_OAUTH_TOKEN])?;
+    }
+    let service_function = service_fn(|event| async { sync_handler(event, &service).await });
     lambda_runtime::run(service_function).await?;
 
     Ok(())
 }
 
-async fn eventbridge_handler(
-    event: LambdaEvent<EventBridgeEvent>,
+async fn sync_handler(
+    event: LambdaEvent<SyncEvent>,
     service: &CommonService,
-) -> Result<Value, Error> {
+) -> Result<DataSyncRun, Error> {
     println!("{:?}", event.payload);
-    match process_event(service).await {
-        Ok(_) => {
-            println!("finish processing event with success!")
+    match process_event(event.payload, service).await {
+        Ok(run) => {
+            println!("finish processing event with success!");
+            Ok(run)
         }
         Err(error) => {
-            println!("Error processing event: {:?}", error)
+            println!("Error processing event: {:?}", error);
+            Err(error.into())
         }
     }
-    return Ok(json!({}));
 }
 
-async fn process_event(service: &CommonService) -> anyhow::Result<()> {
-    service.bedrock.start_data_sync().await?;
-    Ok(())
+async fn process_event(event: SyncEvent, service: &CommonService) -> anyhow::Result<DataSyncRun> {
+    let mut run = match event {
+        SyncEvent::Start(request) => start(service, *request).await?,
+        SyncEvent::Check(mut run) => {
+            run.refresh(service.data_sync.as_ref()).await;
+            run
+        }
+    };
+
+    report_progress(service, &mut run).await;
+    if run.finished {
+        report(service, &run).await;
+    }
+
+    Ok(run)
+}
+
+async fn start(service: &CommonService, request: SyncRequest) -> anyhow::Result<DataSyncRun> {
+    let result = if !request.pages.is_empty() {
+        sync_pages(service, &request).await.map(|job| vec![job])
+    } else {
+        match request.selection(&service.config) {
+            Ok(selection) => service.data_sync.start_data_sync(&selection).await,
+            Err(error) => Err(error),
+        }
+    };
+    match result {
+        Ok(jobs) => {
+            let mut run = DataSyncRun::new(jobs);
+            run.reply = request.reply;
+            Ok(run)
+        }
+        Err(error) => {
+            // ex: a data source name that does not exist, so tell whoever asked.
+            if let Some(reply) = &request.reply {
+                let text = format!("The knowledge base sync could not be started: {}", error);
+                if let Err(error) = service
+                    .slack
+                    .send_text_reply(
+                        &reply.channel_id,
+                        reply.thread_ts.as_deref(),
+                        reply.user_id.as_deref(),
+                        &text,
+                    )
+                    .await
+                {
+                    println!("error replying: {}", error);
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+// only the pages that changed, for a custom data source given them by the Confluence webhooks.
+// a page that is gone is deleted from the data source.
+async fn sync_pages(
+    service: &CommonService,
+    request: &SyncRequest,
+) -> anyhow::Result<IngestionJobReport> {
+    let [data_source] = request.filter.data_sources.as_slice() else {
+        anyhow::bail!(
+            "pages are synced to one data source, got {:?}.",
+            request.filter.data_sources
+        );
+    };
+    let data_source = service.data_sync.data_source(data_source).await?;
+    if !data_source.custom {
+        anyhow::bail!(
+            "{} is not a custom data source, its pages cannot be synced alone.",
+            data_source.name
+        );
+    }
+
+    let mut documents = vec![];
+    let mut deleted = vec![];
+    for id in &request.pages {
+        match service.confluence.page(id).await? {
+            Some(page) => documents.push(page.document()),
+            None => deleted.push(service.confluence.document_id(id)?),
+        }
+    }
+    println!(
+        "syncing pages of {}: {} updated, {} deleted",
+        data_source.name,
+        documents.len(),
+        deleted.len()
+    );
+
+    service
+        .data_sync
+        .sync_documents(&data_source, &documents, &deleted)
+        .await
+}
+
+// for a sync requested from Slack, keep its progress message up to date.
+async fn report_progress(service: &CommonService, run: &mut DataSyncRun) {
+    let Some(reply) = run.reply.clone() else {
+        return;
+    };
+    match service.slack.send_sync_progress(&reply, run).await {
+        Ok(message_ts) => {
+            if let Some(reply) = run.reply.as_mut() {
+                reply.message_ts = Some(message_ts);
+            }
+        }
+        Err(error) => println!("error posting sync progress: {}", error),
+    }
+}
+
+async fn report(service: &CommonService, run: &DataSyncRun) {
+    let summary = run.summary();
+    println!("{}", summary);
+
+    // ex: none of the data sources is due at this hour.
+    if run.jobs.is_empty() && run.reply.is_none() {
+        return;
+    }
+    let Some(channel_id) = &service.config.ops_channel_id else {
+        return;
+    };
+    if let Err(error) = service.slack.send_sync_summary(channel_id, run).await {
+        println!("error posting sync summary: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::{
+        config::Config,
+        service::{
+            bedrock_service::{
+                data_sync::{InMemoryKnowledgeBaseSync, IngestionJobStatus, SyncReply},
+                sync_selection::SyncFilter,
+            },
+            confluence_service::pages::ConfluencePage,
+            slack_service::poster::{POST_MESSAGE_METHOD, UPDATE_MESSAGE_METHOD},
+            InMemoryServices,
+        },
+    };
+    use serde_json::json;
+
+    const OPS_CHANNEL: &str = "C0OPS";
+
+    fn services() -> InMemoryServices {
+        InMemoryServices {
+            data_sync: InMemoryKnowledgeBaseSync::with_data_sources(&["engineering", "hr"])
+                .with_custom_data_sources(&["pages"]),
+            ..Default::default()
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            ops_channel_id: Some(OPS_CHANNEL.to_owned()),
+            confluence_base_url: Some("https://example.atlassian.net".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn request(data_sources: &[&str]) -> SyncRequest {
+        SyncRequest {
+            filter: SyncFilter {
+                data_sources: data_sources.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn reply() -> SyncReply {
+        SyncReply {
+            channel_id: "C0123ABCD".to_owned(),
+            thread_ts: Some("1700000000.000100".to_owned()),
+            user_id: Some("U0123ABCD".to_owned()),
+            message_ts: None,
+        }
+    }
+
+    #[test]
+    fn parses_start_and_check_events() {
+        let scheduled = json!({
+            "detail-type": "Scheduled Event",
+            "time": "2026-10-12T09:00:00Z",
+            "detail": {}
+        });
+        assert!(matches!(
+            serde_json::from_value::<SyncEvent>(scheduled).unwrap(),
+            SyncEvent::Start(_)
+        ));
+
+        let run = serde_json::to_value(DataSyncRun::new(vec![])).unwrap();
+        assert!(matches!(
+            serde_json::from_value::<SyncEvent>(run).unwrap(),
+            SyncEvent::Check(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn starts_then_reports_once_finished() {
+        let services = services();
+        let service = services.service(config());
+
+        let run = process_event(
+            SyncEvent::Start(Box::new(request(&["engineering"]))),
+            &service,
+        )
+        .await
+        .unwrap();
+
+        assert!(!run.finished);
+        assert_eq!(run.jobs.len(), 1);
+        assert_eq!(run.jobs[0].data_source_name, "engineering");
+        assert_eq!(services.data_sync.sync_count(), 1);
+        assert!(services.poster.calls().is_empty());
+
+        let run = process_event(SyncEvent::Check(run), &service)
+            .await
+            .unwrap();
+
+        assert!(run.finished);
+        assert_eq!(run.jobs[0].status, IngestionJobStatus::Complete);
+        let calls = services.poster.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].method, POST_MESSAGE_METHOD);
+        assert_eq!(calls[0].body["channel"], OPS_CHANNEL);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_requester_posted() {
+        let services = services();
+        let service = services.service(config());
+        let request = SyncRequest {
+            reply: Some(reply()),
+            ..request(&["hr"])
+        };
+
+        let run = process_event(SyncEvent::Start(Box::new(request)), &service)
+            .await
+            .unwrap();
+
+        let reply = run.reply.clone().unwrap();
+        assert!(reply.message_ts.is_some());
+        let calls = services.poster.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].method, POST_MESSAGE_METHOD);
+        assert_eq!(calls[0].body["thread_ts"], "1700000000.000100");
+
+        let run = process_event(SyncEvent::Check(run), &service)
+            .await
+            .unwrap();
+
+        assert!(run.finished);
+        let calls = services.poster.calls();
+        let methods: Vec<&str> = calls.iter().map(|call| call.method).collect();
+        // the progress updated, the requester mentioned, then the summary.
+        assert_eq!(
+            methods,
+            vec![
+                POST_MESSAGE_METHOD,
+                UPDATE_MESSAGE_METHOD,
+                POST_MESSAGE_METHOD,
+                POST_MESSAGE_METHOD
+            ]
+        );
+        assert_eq!(calls[1].body["ts"], reply.message_ts.unwrap().as_str());
+        assert!(calls[2].body.to_string().contains("<@U0123ABCD>"));
+        assert_eq!(calls[3].body["channel"], OPS_CHANNEL);
+    }
+
+    #[tokio::test]
+    async fn tells_the_requester_when_it_cannot_start() {
+        let services = services();
+        let service = services.service(config());
+        let request = SyncRequest {
+            reply: Some(reply()),
+            ..request(&["finance"])
+        };
+
+        let result = process_event(SyncEvent::Start(Box::new(request)), &service).await;
+
+        assert!(result.is_err());
+        assert_eq!(services.data_sync.sync_count(), 0);
+        let calls = services.poster.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].body["thread_ts"], "1700000000.000100");
+        assert!(calls[0].body.to_string().contains("could not be started"));
+    }
+
+    #[tokio::test]
+    async fn syncs_the_changed_pages_of_a_custom_data_source() {
+        let services = services();
+        services.pages.set_page(ConfluencePage {
+            id: "1".to_owned(),
+            title: "Runbook".to_owned(),
+            url: "https://example.atlassian.net/wiki/pages/viewpage.action?pageId=1".to_owned(),
+            text: "Restart the service.".to_owned(),
+        });
+        let service = services.service(config());
+        let request = SyncRequest {
+            pages: vec!["1".to_owned(), "2".to_owned()],
+            ..request(&["pages"])
+        };
+
+        let run = process_event(SyncEvent::Start(Box::new(request)), &service)
+            .await
+            .unwrap();
+
+        assert!(run.finished);
+        let statistics = run.jobs[0].statistics.unwrap();
+        assert_eq!(statistics.modified_indexed, 1);
+        assert_eq!(statistics.deleted, 1);
+        let documents = services.data_sync.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].text, "Runbook\n\nRestart the service.");
+    }
+
+    #[tokio::test]
+    async fn pages_are_only_synced_to_a_custom_data_source() {
+        let services = services();
+        let service = services.service(config());
+        let request = SyncRequest {
+            pages: vec!["1".to_owned()],
+            ..request(&["engineering"])
+        };
+
+        let result = process_event(SyncEvent::Start(Box::new(request)), &service).await;
+
+        assert!(result.is_err());
+        assert_eq!(services.data_sync.sync_count(), 0);
+    }
 }
@@ -0,0 +1,677 @@
+use anyhow::{bail, Context, Result};
+use std::{collections::HashMap, env, str::FromStr, time::Duration};
+
+use crate::{
+    env_keys::{
+        ADMIN_USER_GROUP_IDS, ADMIN_USER_IDS, BOT_OAUTH_TOKEN, BOT_OAUTH_TOKEN_ID,
+        CHANNEL_RETRIEVAL_SETTINGS, CHAT_MODEL_ID, CONFLUENCE_API_TOKEN, CONFLUENCE_API_TOKEN_ID,
+        CONFLUENCE_BASE_URL, CONFLUENCE_DATA_SOURCES, CONFLUENCE_DEBOUNCE_SECONDS,
+        CONFLUENCE_EMAIL, CONFLUENCE_WEBHOOK_SECRET, CONFLUENCE_WEBHOOK_SECRET_ID,
+        FEEDBACK_TABLE_NAME, IDEMPOTENCY_TABLE_NAME, KNOWLEDGE_BASE_ID, MAX_RECEIVE_COUNT,
+        MODEL_MAX_TOKENS, MODEL_TEMPERATURE, MODEL_TOP_P, OPS_CHANNEL_ID,
+        PRESIGNED_URL_EXPIRY_SECONDS, QUEUE_ARN, QUEUE_URL, RETRIEVAL_NUMBER_OF_RESULTS,
+        RETRIEVAL_SEARCH_TYPE, SECRETS_CACHE_TTL_SECONDS, SESSION_TABLE_NAME, SLACK_API_BASE_URL,
+        SLACK_SIGNING_SECRET, SLACK_SIGNING_SECRET_ID, STREAMING_ENABLED, SYNC_CONFLICT_POLICY,
+        SYNC_DATA_SOURCES, SYNC_EXCLUDE, SYNC_FREQUENCIES, SYNC_INCLUDE, SYNC_STATE_MACHINE_ARN,
+    },
+    secrets::{SecretSource, DEFAULT_SECRETS_CACHE_TTL},
+    service::{
+        bedrock_service::{
+            data_sync::SyncConflictPolicy,
+            settings::{
+                check_positive, check_range, RetrievalSettings, SearchType,
+                NUMBER_OF_RESULTS_RANGE, PROBABILITY_RANGE,
+            },
+            sync_selection::{SyncFilter, SyncFrequency},
+        },
+        confluence_service::{DEFAULT_DEBOUNCE, DEFAULT_SPACE_KEY},
+        s3_presigner::{DEFAULT_PRESIGNED_URL_EXPIRY, MAX_PRESIGNED_URL_EXPIRY},
+        sqs_service::DEFAULT_MAX_RECEIVE_COUNT,
+    },
+};
+
+const DEFAULT_SLACK_API_BASE_URL: &str = "https://slack.com/api";
+
+/// Settings of the lambdas, loaded once at cold start.
+///
+/// Each lambda only needs some of them, so every value is optional here
+/// and the ones a lambda cannot run without are checked with [`Config::require`].
+/// In tests, build it directly, ex: `Config { knowledge_base_id: Some("KB".to_owned()), ..Default::default() }`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Plain values, for local runs.
+    pub slack_signing_secret: Option<String>,
+    pub bot_oauth_token: Option<String>,
+    /// Secrets Manager / Parameter Store ids, used over the plain values when set.
+    pub slack_signing_secret_id: Option<String>,
+    pub bot_oauth_token_id: Option<String>,
+    /// How long a secret is cached before being fetched again, 5 minutes by default.
+    pub secrets_cache_ttl_seconds: Option<u64>,
+    /// `https://slack.com/api` by default.
+    pub slack_api_base_url: Option<String>,
+    pub queue_url: Option<String>,
+    pub queue_arn: Option<String>,
+    /// The `maxReceiveCount` of the dead-letter queue, 3 by default.
+    pub max_receive_count: Option<u32>,
+    pub chat_model_id: Option<String>,
+    pub knowledge_base_id: Option<String>,
+    /// How long the links to S3 sources are valid for, 1 hour by default.
+    pub presigned_url_expiry_seconds: Option<u64>,
+    /// For every channel, unless overridden in `channel_retrieval_settings`.
+    pub retrieval_settings: RetrievalSettings,
+    /// By channel ID.
+    pub channel_retrieval_settings: HashMap<String, RetrievalSettings>,
+    /// Without a table, the store is kept in memory.
+    pub session_table_name: Option<String>,
+    pub feedback_table_name: Option<String>,
+    pub idempotency_table_name: Option<String>,
+    /// `false` by default.
+    pub streaming_enabled: bool,
+    /// Without a channel, the sync summaries are only logged.
+    pub ops_channel_id: Option<String>,
+    /// [`SyncConflictPolicy::Skip`] by default.
+    pub sync_conflict_policy: SyncConflictPolicy,
+    /// The data sources to sync unless the sync is given some, all of them if empty.
+    pub sync_filter: SyncFilter,
+    /// How often the scheduled sync syncs each data source, by ID or name.
+    pub sync_frequencies: HashMap<String, SyncFrequency>,
+    /// Slack users allowed to run the admin commands, directly or through one of the user groups.
+    /// Nobody is if both are empty.
+    pub admin_user_ids: Vec<String>,
+    pub admin_user_group_ids: Vec<String>,
+    /// Started by the `admin sync` command and the Confluence webhooks.
+    pub sync_state_machine_arn: Option<String>,
+    /// Plain values and ids, as for the Slack secrets.
+    pub confluence_webhook_secret: Option<String>,
+    pub confluence_webhook_secret_id: Option<String>,
+    pub confluence_api_token: Option<String>,
+    pub confluence_api_token_id: Option<String>,
+    /// The data source each Confluence space is synced to when its pages change, by space key.
+    /// The webhooks are ignored if empty.
+    pub confluence_data_sources: HashMap<String, String>,
+    /// 5 minutes by default.
+    pub confluence_debounce_seconds: Option<u64>,
+    /// ex: `https://example.atlassian.net`.
+    pub confluence_base_url: Option<String>,
+    pub confluence_email: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|key| env::var(key).ok())
+    }
+
+    /// Load from any source of variables, ex: a `HashMap` in tests.
+    /// Empty values are treated as unset.
+    pub fn from_vars(vars: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |key: &str| vars(key).filter(|v| !v.trim().is_empty());
+
+        let queue_url = var(QUEUE_URL);
+        if let Some(url) = &queue_url {
+            if !url.starts_with("https://") {
+                bail!("{} should be an https URL, got `{}`.", QUEUE_URL, url);
+            }
+        }
+
+        let slack_api_base_url =
+            var(SLACK_API_BASE_URL).map(|url| url.trim_end_matches('/').to_owned());
+        if let Some(url) = &slack_api_base_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                bail!(
+                    "{} should be an http(s) URL, got `{}`.",
+                    SLACK_API_BASE_URL,
+                    url
+                );
+            }
+        }
+
+        let queue_arn = var(QUEUE_ARN);
+        if let Some(arn) = &queue_arn {
+            if !arn.starts_with("arn:") {
+                bail!("{} should be an ARN, got `{}`.", QUEUE_ARN, arn);
+            }
+        }
+
+        let max_receive_count = match var(MAX_RECEIVE_COUNT) {
+            None => None,
+            Some(v) => match v.parse::<u32>() {
+                Ok(count) if count > 0 => Some(count),
+                _ => bail!(
+                    "{} should be a positive number, got `{}`.",
+                    MAX_RECEIVE_COUNT,
+                    v
+                ),
+            },
+        };
+
+        let secrets_cache_ttl_seconds = match var(SECRETS_CACHE_TTL_SECONDS) {
+            None => None,
+            Some(v) => match v.parse::<u64>() {
+                Ok(seconds) => Some(seconds),
+                Err(_) => bail!(
+                    "{} should be a number of seconds, got `{}`.",
+                    SECRETS_CACHE_TTL_SECONDS,
+                    v
+                ),
+            },
+        };
+
+        let presigned_url_expiry_seconds = match var(PRESIGNED_URL_EXPIRY_SECONDS) {
+            None => None,
+            Some(v) => match v.parse::<u64>() {
+                Ok(seconds) if seconds > 0 && seconds <= MAX_PRESIGNED_URL_EXPIRY.as_secs() => {
+                    Some(seconds)
+                }
+                _ => bail!(
+                    "{} should be a number of seconds up to {}, got `{}`.",
+                    PRESIGNED_URL_EXPIRY_SECONDS,
+                    MAX_PRESIGNED_URL_EXPIRY.as_secs(),
+                    v
+                ),
+            },
+        };
+
+        let search_type = match var(RETRIEVAL_SEARCH_TYPE) {
+            None => None,
+            Some(v) => match SearchType::from_name(&v) {
+                Some(search_type) => Some(search_type),
+                None => bail!(
+                    "{} should be `HYBRID` or `SEMANTIC`, got `{}`.",
+                    RETRIEVAL_SEARCH_TYPE,
+                    v
+                ),
+            },
+        };
+        let retrieval_settings = RetrievalSettings {
+            number_of_results: parse_number(
+                RETRIEVAL_NUMBER_OF_RESULTS,
+                var(RETRIEVAL_NUMBER_OF_RESULTS),
+            )?,
+            search_type,
+            temperature: parse_number(MODEL_TEMPERATURE, var(MODEL_TEMPERATURE))?,
+            max_tokens: parse_number(MODEL_MAX_TOKENS, var(MODEL_MAX_TOKENS))?,
+            top_p: parse_number(MODEL_TOP_P, var(MODEL_TOP_P))?,
+        };
+        check_range(
+            RETRIEVAL_NUMBER_OF_RESULTS,
+            retrieval_settings.number_of_results,
+            NUMBER_OF_RESULTS_RANGE,
+        )?;
+        check_range(
+            MODEL_TEMPERATURE,
+            retrieval_settings.temperature,
+            PROBABILITY_RANGE,
+        )?;
+        check_range(MODEL_TOP_P, retrieval_settings.top_p, PROBABILITY_RANGE)?;
+        check_positive(MODEL_MAX_TOKENS, retrieval_settings.max_tokens)?;
+
+        let channel_retrieval_settings = match var(CHANNEL_RETRIEVAL_SETTINGS) {
+            None => HashMap::new(),
+            Some(v) => {
+                let settings = serde_json::from_str::<HashMap<String, RetrievalSettings>>(&v)
+                    .with_context(|| {
+                        format!(
+                            "{} should be a JSON object of channel IDs to settings, got `{}`.",
+                            CHANNEL_RETRIEVAL_SETTINGS, v
+                        )
+                    })?;
+                for (channel_id, settings) in &settings {
+                    settings.validate().with_context(|| {
+                        format!(
+                            "invalid settings for {} in {}.",
+                            channel_id, CHANNEL_RETRIEVAL_SETTINGS
+                        )
+                    })?;
+                }
+                settings
+            }
+        };
+
+        let sync_conflict_policy = match var(SYNC_CONFLICT_POLICY) {
+            None => SyncConflictPolicy::default(),
+            Some(v) => match SyncConflictPolicy::from_name(&v) {
+                Some(policy) => policy,
+                None => bail!(
+                    "{} should be `skip`, `wait` or `restart`, got `{}`.",
+                    SYNC_CONFLICT_POLICY,
+                    v
+                ),
+            },
+        };
+
+        let sync_filter = SyncFilter {
+            data_sources: parse_list(var(SYNC_DATA_SOURCES)),
+            include: parse_list(var(SYNC_INCLUDE)),
+            exclude: parse_list(var(SYNC_EXCLUDE)),
+        };
+
+        let sync_frequencies = match var(SYNC_FREQUENCIES) {
+            None => HashMap::new(),
+            Some(v) => serde_json::from_str::<HashMap<String, SyncFrequency>>(&v).with_context(|| {
+                format!(
+                    "{} should be a JSON object of data source IDs or names to `hourly`, `daily`, `weekdays` or `weekly`, got `{}`.",
+                    SYNC_FREQUENCIES, v
+                )
+            })?,
+        };
+
+        let admin_user_ids = parse_slack_ids(ADMIN_USER_IDS, var(ADMIN_USER_IDS), &['U', 'W'])?;
+        let admin_user_group_ids =
+            parse_slack_ids(ADMIN_USER_GROUP_IDS, var(ADMIN_USER_GROUP_IDS), &['S'])?;
+
+        let sync_state_machine_arn = var(SYNC_STATE_MACHINE_ARN);
+        if let Some(arn) = &sync_state_machine_arn {
+            if !arn.starts_with("arn:") {
+                bail!(
+                    "{} should be an ARN, got `{}`.",
+                    SYNC_STATE_MACHINE_ARN,
+                    arn
+                );
+            }
+        }
+
+        let confluence_data_sources = match var(CONFLUENCE_DATA_SOURCES) {
+            None => HashMap::new(),
+            Some(v) => serde_json::from_str::<HashMap<String, String>>(&v).with_context(|| {
+                format!(
+                    "{} should be a JSON object of space keys to data source IDs or names, got `{}`.",
+                    CONFLUENCE_DATA_SOURCES, v
+                )
+            })?,
+        };
+
+        let confluence_debounce_seconds = match var(CONFLUENCE_DEBOUNCE_SECONDS) {
+            None => None,
+            Some(v) => match v.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => Some(seconds),
+                _ => bail!(
+                    "{} should be a positive number of seconds, got `{}`.",
+                    CONFLUENCE_DEBOUNCE_SECONDS,
+                    v
+                ),
+            },
+        };
+
+        // the site, whether given with `/wiki` or not.
+        let confluence_base_url = var(CONFLUENCE_BASE_URL).map(|url| {
+            url.trim_end_matches('/')
+                .trim_end_matches("/wiki")
+                .to_owned()
+        });
+        if let Some(url) = &confluence_base_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                bail!(
+                    "{} should be an http(s) URL, got `{}`.",
+                    CONFLUENCE_BASE_URL,
+                    url
+                );
+            }
+        }
+
+        let streaming_enabled = match var(STREAMING_ENABLED) {
+            None => false,
+            Some(v) => match v.to_lowercase().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => bail!(
+                    "{} should be `true` or `false`, got `{}`.",
+                    STREAMING_ENABLED,
+                    v
+                ),
+            },
+        };
+
+        Ok(Self {
+            slack_signing_secret: var(SLACK_SIGNING_SECRET),
+            bot_oauth_token: var(BOT_OAUTH_TOKEN),
+            slack_signing_secret_id: var(SLACK_SIGNING_SECRET_ID),
+            bot_oauth_token_id: var(BOT_OAUTH_TOKEN_ID),
+            secrets_cache_ttl_seconds,
+            slack_api_base_url,
+            queue_url,
+            queue_arn,
+            max_receive_count,
+            chat_model_id: var(CHAT_MODEL_ID),
+            knowledge_base_id: var(KNOWLEDGE_BASE_ID),
+            presigned_url_expiry_seconds,
+            retrieval_settings,
+            channel_retrieval_settings,
+            session_table_name: var(SESSION_TABLE_NAME),
+            feedback_table_name: var(FEEDBACK_TABLE_NAME),
+            idempotency_table_name: var(IDEMPOTENCY_TABLE_NAME),
+            streaming_enabled,
+            ops_channel_id: var(OPS_CHANNEL_ID),
+            sync_conflict_policy,
+            sync_filter,
+            sync_frequencies,
+            admin_user_ids,
+            admin_user_group_ids,
+            sync_state_machine_arn,
+            confluence_webhook_secret: var(CONFLUENCE_WEBHOOK_SECRET),
+            confluence_webhook_secret_id: var(CONFLUENCE_WEBHOOK_SECRET_ID),
+            confluence_api_token: var(CONFLUENCE_API_TOKEN),
+            confluence_api_token_id: var(CONFLUENCE_API_TOKEN_ID),
+            confluence_data_sources,
+            confluence_debounce_seconds,
+            confluence_base_url,
+            confluence_email: var(CONFLUENCE_EMAIL),
+        })
+    }
+
+    /// Fail with all of the `keys` (from [`crate::env_keys`]) that are not set.
+    pub fn require(&self, keys: &[&str]) -> Result<()> {
+        let mut missing = vec![];
+        for key in keys {
+            if self.get(key)?.is_none() {
+                missing.push(*key);
+            }
+        }
+
+        if !missing.is_empty() {
+            bail!("missing required configuration: {}.", missing.join(", "))
+        }
+
+        Ok(())
+    }
+
+    pub fn slack_signing_secret_source(&self) -> Option<SecretSource> {
+        secret_source(&self.slack_signing_secret_id, &self.slack_signing_secret)
+    }
+
+    pub fn bot_oauth_token_source(&self) -> Option<SecretSource> {
+        secret_source(&self.bot_oauth_token_id, &self.bot_oauth_token)
+    }
+
+    pub fn confluence_webhook_secret_source(&self) -> Option<SecretSource> {
+        secret_source(
+            &self.confluence_webhook_secret_id,
+            &self.confluence_webhook_secret,
+        )
+    }
+
+    pub fn confluence_api_token_source(&self) -> Option<SecretSource> {
+        secret_source(&self.confluence_api_token_id, &self.confluence_api_token)
+    }
+
+    pub fn slack_api_base_url(&self) -> &str {
+        self.slack_api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_SLACK_API_BASE_URL)
+    }
+
+    pub fn max_receive_count(&self) -> u32 {
+        self.max_receive_count.unwrap_or(DEFAULT_MAX_RECEIVE_COUNT)
+    }
+
+    pub fn secrets_cache_ttl(&self) -> Duration {
+        self.secrets_cache_ttl_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SECRETS_CACHE_TTL)
+    }
+
+    pub fn presigned_url_expiry(&self) -> Duration {
+        self.presigned_url_expiry_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_PRESIGNED_URL_EXPIRY)
+    }
+
+    pub fn confluence_debounce(&self) -> Duration {
+        self.confluence_debounce_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_DEBOUNCE)
+    }
+
+    /// The data source the pages of the space with `space_key` are synced to, if any.
+    pub fn confluence_data_source(&self, space_key: &str) -> Option<&str> {
+        self.confluence_data_sources
+            .get(space_key)
+            .or_else(|| self.confluence_data_sources.get(DEFAULT_SPACE_KEY))
+            .map(|data_source| data_source.as_str())
+    }
+
+    /// The settings to answer with in `channel_id`.
+    pub fn retrieval_settings(&self, channel_id: &str) -> RetrievalSettings {
+        match self.channel_retrieval_settings.get(channel_id) {
+            Some(overrides) => self.retrieval_settings.merge(overrides),
+            None => self.retrieval_settings,
+        }
+    }
+
+    // a secret is there if either its value or its id is.
+    fn get(&self, key: &str) -> Result<Option<&str>> {
+        let value = match key {
+            k if k == SLACK_SIGNING_SECRET => {
+                return Ok(self
+                    .slack_signing_secret_id
+                    .as_deref()
+                    .or(self.slack_signing_secret.as_deref()))
+            }
+            k if k == BOT_OAUTH_TOKEN => {
+                return Ok(self
+                    .bot_oauth_token_id
+                    .as_deref()
+                    .or(self.bot_oauth_token.as_deref()))
+            }
+            k if k == CONFLUENCE_WEBHOOK_SECRET => {
+                return Ok(self
+                    .confluence_webhook_secret_id
+                    .as_deref()
+                    .or(self.confluence_webhook_secret.as_deref()))
+            }
+            k if k == CONFLUENCE_API_TOKEN => {
+                return Ok(self
+                    .confluence_api_token_id
+                    .as_deref()
+                    .or(self.confluence_api_token.as_deref()))
+            }
+            k if k == CONFLUENCE_BASE_URL => &self.confluence_base_url,
+            k if k == CONFLUENCE_EMAIL => &self.confluence_email,
+            k if k == QUEUE_URL => &self.queue_url,
+            k if k == QUEUE_ARN => &self.queue_arn,
+            k if k == CHAT_MODEL_ID => &self.chat_model_id,
+            k if k == KNOWLEDGE_BASE_ID => &self.knowledge_base_id,
+            k if k == SESSION_TABLE_NAME => &self.session_table_name,
+            k if k == FEEDBACK_TABLE_NAME => &self.feedback_table_name,
+            k if k == IDEMPOTENCY_TABLE_NAME => &self.idempotency_table_name,
+            k if k == OPS_CHANNEL_ID => &self.ops_channel_id,
+            k if k == SYNC_STATE_MACHINE_ARN => &self.sync_state_machine_arn,
+            _ => bail!("{} is not a required configuration.", key),
+        };
+
+        Ok(value.as_deref())
+    }
+}
+
+fn parse_number<T: FromStr>(key: &str, value: Option<String>) -> Result<Option<T>> {
+    match value {
+        None => Ok(None),
+        Some(v) => match v.trim().parse::<T>() {
+            Ok(number) => Ok(Some(number)),
+            Err(_) => bail!("{} should be a number, got `{}`.", key, v),
+        },
+    }
+}
+
+// comma separated values, the blank ones left out.
+fn parse_list(value: Option<String>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+        .collect()
+}
+
+// comma separated Slack IDs, starting with one of `prefixes`.
+fn parse_slack_ids(key: &str, value: Option<String>, prefixes: &[char]) -> Result<Vec<String>> {
+    let Some(value) = value else {
+        return Ok(vec![]);
+    };
+
+    let mut ids = vec![];
+    for id in value
+        .split(',')
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+    {
+        if !id.starts_with(prefixes) || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            let prefixes: Vec<String> = prefixes.iter().map(|p| format!("`{}`", p)).collect();
+            bail!(
+                "{} should be comma separated IDs starting with {}, got `{}`.",
+                key,
+                prefixes.join(" or "),
+                id
+            );
+        }
+        ids.push(id.to_owned());
+    }
+
+    Ok(ids)
+}
+
+fn secret_source(id: &Option<String>, value: &Option<String>) -> Option<SecretSource> {
+    match (id, value) {
+        (Some(id), _) => Some(SecretSource::from_id(id)),
+        (None, Some(value)) => Some(SecretSource::Value(value.to_owned())),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::from_vars(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn defaults_when_nothing_is_set() {
+        let config = config(&[]).unwrap();
+
+        assert_eq!(config, Config::default());
+        assert_eq!(config.slack_api_base_url(), DEFAULT_SLACK_API_BASE_URL);
+        assert_eq!(config.secrets_cache_ttl(), DEFAULT_SECRETS_CACHE_TTL);
+        assert_eq!(config.presigned_url_expiry(), DEFAULT_PRESIGNED_URL_EXPIRY);
+        assert_eq!(config.confluence_debounce(), DEFAULT_DEBOUNCE);
+        assert_eq!(config.max_receive_count(), DEFAULT_MAX_RECEIVE_COUNT);
+        assert_eq!(config.sync_conflict_policy, SyncConflictPolicy::Skip);
+        assert!(!config.streaming_enabled);
+        assert_eq!(config.slack_signing_secret_source(), None);
+        assert_eq!(config.confluence_data_source("ENG"), None);
+    }
+
+    #[test]
+    fn empty_values_are_unset() {
+        let config = config(&[
+            (QUEUE_URL, ""),
+            (QUEUE_ARN, "  "),
+            (KNOWLEDGE_BASE_ID, ""),
+            (SECRETS_CACHE_TTL_SECONDS, ""),
+            (STREAMING_ENABLED, " "),
+            (SYNC_CONFLICT_POLICY, ""),
+            (ADMIN_USER_IDS, ""),
+            (CONFLUENCE_DATA_SOURCES, ""),
+            (MAX_RECEIVE_COUNT, ""),
+        ])
+        .unwrap();
+
+        assert_eq!(config, Config::default());
+        assert!(config.require(&[KNOWLEDGE_BASE_ID]).is_err());
+    }
+
+    #[test]
+    fn missing_required_keys_are_all_reported() {
+        let config = config(&[(KNOWLEDGE_BASE_ID, "KB")]).unwrap();
+
+        assert!(config.require(&[KNOWLEDGE_BASE_ID]).is_ok());
+        let error = config
+            .require(&[QUEUE_URL, KNOWLEDGE_BASE_ID, CHAT_MODEL_ID])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "missing required configuration: {}, {}.",
+                QUEUE_URL, CHAT_MODEL_ID
+            )
+        );
+    }
+
+    #[test]
+    fn secrets_are_there_with_either_their_value_or_their_id() {
+        let config = config(&[
+            (SLACK_SIGNING_SECRET, "secret"),
+            (BOT_OAUTH_TOKEN_ID, "/slack/bot-token"),
+        ])
+        .unwrap();
+
+        assert!(config
+            .require(&[SLACK_SIGNING_SECRET, BOT_OAUTH_TOKEN])
+            .is_ok());
+        assert!(config.require(&[CONFLUENCE_WEBHOOK_SECRET]).is_err());
+        assert_eq!(
+            config.slack_signing_secret_source(),
+            Some(SecretSource::Value("secret".to_owned()))
+        );
+    }
+
+    #[test]
+    fn unknown_required_keys_fail() {
+        assert!(Config::default().require(&["NOT_A_KEY"]).is_err());
+    }
+
+    #[test]
+    fn values_are_parsed() {
+        let config = config(&[
+            (SLACK_API_BASE_URL, "http://localhost:8080/api/"),
+            (CONFLUENCE_BASE_URL, "https://example.atlassian.net/wiki/"),
+            (STREAMING_ENABLED, "TRUE"),
+            (MAX_RECEIVE_COUNT, "5"),
+            (SYNC_DATA_SOURCES, " DS1, ,eng-* "),
+            (ADMIN_USER_IDS, "U0123ABCD, W0123ABCD"),
+            (CONFLUENCE_DATA_SOURCES, r#"{"ENG": "DS1", "*": "DS2"}"#),
+        ])
+        .unwrap();
+
+        assert_eq!(config.slack_api_base_url(), "http://localhost:8080/api");
+        assert_eq!(
+            config.confluence_base_url.as_deref(),
+            Some("https://example.atlassian.net")
+        );
+        assert!(config.streaming_enabled);
+        assert_eq!(config.max_receive_count(), 5);
+        assert_eq!(config.sync_filter.data_sources, vec!["DS1", "eng-*"]);
+        assert_eq!(config.admin_user_ids, vec!["U0123ABCD", "W0123ABCD"]);
+        assert_eq!(config.confluence_data_source("ENG"), Some("DS1"));
+        assert_eq!(config.confluence_data_source("HR"), Some("DS2"));
+    }
+
+    #[test]
+    fn invalid_values_fail() {
+        let invalid = [
+            (QUEUE_URL, "http://sqs.example.com"),
+            (QUEUE_ARN, "queue"),
+            (SLACK_API_BASE_URL, "slack.com/api"),
+            (SECRETS_CACHE_TTL_SECONDS, "five"),
+            (PRESIGNED_URL_EXPIRY_SECONDS, "0"),
+            (MAX_RECEIVE_COUNT, "0"),
+            (STREAMING_ENABLED, "yes"),
+            (RETRIEVAL_SEARCH_TYPE, "KEYWORD"),
+            (MODEL_TEMPERATURE, "2"),
+            (SYNC_CONFLICT_POLICY, "later"),
+            (SYNC_FREQUENCIES, r#"{"DS1": "monthly"}"#),
+            (ADMIN_USER_IDS, "C0123ABCD"),
+            (CONFLUENCE_DEBOUNCE_SECONDS, "0"),
+            (CONFLUENCE_DATA_SOURCES, "ENG"),
+        ];
+        for (key, value) in invalid {
+            assert!(config(&[(key, value)]).is_err(), "{}=`{}`", key, value);
+        }
+    }
+}
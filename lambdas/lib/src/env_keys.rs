@@ -3,6 +3,72 @@ pub static BOT_OAUTH_TOKEN: &str = "BOT_OAUTH_TOKEN";
 
 pub static QUEUE_URL: &str = "QUEUE_URL";
 pub static QUEUE_ARN: &str = "QUEUE_ARN";
+// the `maxReceiveCount` of the dead-letter queue, 3 by default.
+// on the last attempt, the asker is told about the failure instead of the message being retried.
+pub static MAX_RECEIVE_COUNT: &str = "MAX_RECEIVE_COUNT";
 
 pub static CHAT_MODEL_ID: &str = "CHAT_MODEL_ID";
 pub static KNOWLEDGE_BASE_ID: &str = "KNOWLEDGE_BASE_ID";
+
+pub static SESSION_TABLE_NAME: &str = "SESSION_TABLE_NAME";
+pub static FEEDBACK_TABLE_NAME: &str = "FEEDBACK_TABLE_NAME";
+pub static IDEMPOTENCY_TABLE_NAME: &str = "IDEMPOTENCY_TABLE_NAME";
+
+pub static STREAMING_ENABLED: &str = "STREAMING_ENABLED";
+
+// Secrets Manager secret (name or ARN) or Parameter Store parameter (`/name` or ARN)
+// to read the value from, instead of the plain env var above.
+pub static SLACK_SIGNING_SECRET_ID: &str = "SLACK_SIGNING_SECRET_ID";
+pub static BOT_OAUTH_TOKEN_ID: &str = "BOT_OAUTH_TOKEN_ID";
+pub static SECRETS_CACHE_TTL_SECONDS: &str = "SECRETS_CACHE_TTL_SECONDS";
+
+// ex: a local mock Slack server for tests.
+pub static SLACK_API_BASE_URL: &str = "SLACK_API_BASE_URL";
+
+// how long the links to S3 sources in the answers are valid for, 1 hour by default.
+pub static PRESIGNED_URL_EXPIRY_SECONDS: &str = "PRESIGNED_URL_EXPIRY_SECONDS";
+
+// retrieval and generation, see `RetrievalSettings`. Unset values are left to Bedrock.
+pub static RETRIEVAL_NUMBER_OF_RESULTS: &str = "RETRIEVAL_NUMBER_OF_RESULTS";
+// `HYBRID` or `SEMANTIC`.
+pub static RETRIEVAL_SEARCH_TYPE: &str = "RETRIEVAL_SEARCH_TYPE";
+pub static MODEL_TEMPERATURE: &str = "MODEL_TEMPERATURE";
+pub static MODEL_MAX_TOKENS: &str = "MODEL_MAX_TOKENS";
+pub static MODEL_TOP_P: &str = "MODEL_TOP_P";
+// JSON object of channel IDs to the settings overriding the ones above in that channel.
+pub static CHANNEL_RETRIEVAL_SETTINGS: &str = "CHANNEL_RETRIEVAL_SETTINGS";
+
+// what to do when a data source is already being synced: `skip` (default), `wait` or `restart`.
+pub static SYNC_CONFLICT_POLICY: &str = "SYNC_CONFLICT_POLICY";
+
+// the data sources synced when the sync is not given any: comma separated IDs or names,
+// or patterns of the ones to include and exclude, ex: `eng-*`. All of them by default.
+pub static SYNC_DATA_SOURCES: &str = "SYNC_DATA_SOURCES";
+pub static SYNC_INCLUDE: &str = "SYNC_INCLUDE";
+pub static SYNC_EXCLUDE: &str = "SYNC_EXCLUDE";
+// JSON object of data source IDs or names (`*` for the others) to how often the scheduled sync syncs them:
+// `hourly`, `daily`, `weekdays` (default) or `weekly`.
+pub static SYNC_FREQUENCIES: &str = "SYNC_FREQUENCIES";
+
+// Slack channel the knowledge base sync summaries are posted to.
+pub static OPS_CHANNEL_ID: &str = "OPS_CHANNEL_ID";
+
+// comma separated Slack user IDs and user group IDs allowed to run the admin commands, ex: `admin sync`.
+pub static ADMIN_USER_IDS: &str = "ADMIN_USER_IDS";
+pub static ADMIN_USER_GROUP_IDS: &str = "ADMIN_USER_GROUP_IDS";
+// the state machine running the knowledge base syncs, started by `admin sync`.
+pub static SYNC_STATE_MACHINE_ARN: &str = "SYNC_STATE_MACHINE_ARN";
+
+// Confluence Cloud webhooks syncing the pages as they change: the secret they are signed with (or its id),
+// and a JSON object of space keys (`*` for the others) to the data source ID or name each space is synced to.
+pub static CONFLUENCE_WEBHOOK_SECRET: &str = "CONFLUENCE_WEBHOOK_SECRET";
+pub static CONFLUENCE_WEBHOOK_SECRET_ID: &str = "CONFLUENCE_WEBHOOK_SECRET_ID";
+pub static CONFLUENCE_DATA_SOURCES: &str = "CONFLUENCE_DATA_SOURCES";
+// the changes of a data source within this many seconds are synced together, 300 by default.
+pub static CONFLUENCE_DEBOUNCE_SECONDS: &str = "CONFLUENCE_DEBOUNCE_SECONDS";
+// to fetch the changed pages of custom data sources: the site, ex: `https://example.atlassian.net`,
+// and an API token (or its id) of the account with the email.
+pub static CONFLUENCE_BASE_URL: &str = "CONFLUENCE_BASE_URL";
+pub static CONFLUENCE_EMAIL: &str = "CONFLUENCE_EMAIL";
+pub static CONFLUENCE_API_TOKEN: &str = "CONFLUENCE_API_TOKEN";
+pub static CONFLUENCE_API_TOKEN_ID: &str = "CONFLUENCE_API_TOKEN_ID";
@@ -0,0 +1,195 @@
+use aws_sdk_bedrockagentruntime::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::{
+        retrieve::RetrieveError, retrieve_and_generate::RetrieveAndGenerateError,
+        retrieve_and_generate_stream::RetrieveAndGenerateStreamError,
+    },
+    types::error::RetrieveAndGenerateStreamResponseOutputError,
+};
+use aws_smithy_types::event_stream::RawMessage;
+use reqwest::StatusCode;
+
+use crate::service::slack_service::{SlackApiError, SlackHttpError};
+
+const THROTTLING_ERROR_CODES: [&str; 2] = ["ThrottlingException", "ServiceQuotaExceededException"];
+
+// service errors that might succeed when tried again later.
+const RETRYABLE_ERROR_CODES: [&str; 5] = [
+    "ThrottlingException",
+    "InternalServerException",
+    "ServiceUnavailableException",
+    "BadGatewayException",
+    "DependencyFailedException",
+];
+
+/// Why a question could not be answered, as told to the asker.
+#[derive(Debug)]
+pub enum AnswerError {
+    /// Nothing relevant to the question was found in the knowledge base.
+    NoRelevantContent,
+    /// Bedrock or Slack asked us to slow down.
+    Throttled(anyhow::Error),
+    /// A Bedrock guardrail blocked the question or the answer.
+    GuardrailBlocked,
+    /// Slack refused the answer as too long (`msg_too_long`).
+    AnswerTooLong,
+    Internal(anyhow::Error),
+}
+
+impl AnswerError {
+    /// Message for the asker, without any detail of the underlying error.
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            AnswerError::NoRelevantContent => {
+                "Sorry, I couldn't find anything in Confluence related to your question. Try rephrasing it or adding more details."
+            }
+            AnswerError::Throttled(_) => {
+                "Sorry, I'm getting too many questions right now. Please try again in a minute."
+            }
+            AnswerError::GuardrailBlocked => {
+                "Sorry, I can't help with this question as it goes against the usage policy."
+            }
+            AnswerError::AnswerTooLong => {
+                "Sorry, my answer is too long to post in Slack. Try asking a narrower question."
+            }
+            AnswerError::Internal(_) => {
+                "Sorry, something went wrong while answering your question. Please try again later."
+            }
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AnswerError::Throttled(_) => true,
+            AnswerError::Internal(error) => is_retryable(error),
+            AnswerError::NoRelevantContent
+            | AnswerError::GuardrailBlocked
+            | AnswerError::AnswerTooLong => false,
+        }
+    }
+
+    pub fn slack_api_error(&self) -> Option<&SlackApiError> {
+        match self {
+            AnswerError::Throttled(error) | AnswerError::Internal(error) => slack_api_error(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AnswerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnswerError::NoRelevantContent => write!(f, "no relevant content found"),
+            AnswerError::Throttled(error) => write!(f, "throttled: {}", error),
+            AnswerError::GuardrailBlocked => write!(f, "blocked by guardrail"),
+            AnswerError::AnswerTooLong => write!(f, "answer too long for slack"),
+            AnswerError::Internal(error) => write!(f, "internal error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AnswerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnswerError::Throttled(error) | AnswerError::Internal(error) => Some(error.as_ref()),
+            AnswerError::NoRelevantContent
+            | AnswerError::GuardrailBlocked
+            | AnswerError::AnswerTooLong => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for AnswerError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<AnswerError>() {
+            Ok(answer_error) => return answer_error,
+            Err(error) => error,
+        };
+        if is_throttled(&error) {
+            AnswerError::Throttled(error)
+        } else if slack_api_error(&error) == Some(&SlackApiError::MessageTooLong) {
+            AnswerError::AnswerTooLong
+        } else {
+            AnswerError::Internal(error)
+        }
+    }
+}
+
+fn is_throttled(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<SlackHttpError>() {
+            return e.status == StatusCode::TOO_MANY_REQUESTS;
+        }
+        if let Some(SlackApiError::RateLimited { .. }) = cause.downcast_ref::<SlackApiError>() {
+            return true;
+        }
+        sdk_error_code(cause).is_some_and(|code| THROTTLING_ERROR_CODES.contains(&code))
+    })
+}
+
+fn sdk_error_code<'a>(cause: &'a (dyn std::error::Error + 'static)) -> Option<&'a str> {
+    if let Some(e) = cause.downcast_ref::<SdkError<RetrieveError>>() {
+        return e.code();
+    }
+    if let Some(e) = cause.downcast_ref::<SdkError<RetrieveAndGenerateError>>() {
+        return e.code();
+    }
+    if let Some(e) = cause.downcast_ref::<SdkError<RetrieveAndGenerateStreamError>>() {
+        return e.code();
+    }
+    cause
+        .downcast_ref::<SdkError<RetrieveAndGenerateStreamResponseOutputError, RawMessage>>()
+        .and_then(|e| e.code())
+}
+
+/// Whether the failure is temporary (throttling, timeouts, Slack 5xx, ...),
+/// so that the request is worth retrying.
+/// Anything else, ex: a malformed message, fails the same way every time.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<SdkError<RetrieveError>>() {
+            return is_retryable_sdk_error(e);
+        }
+        if let Some(e) = cause.downcast_ref::<SdkError<RetrieveAndGenerateError>>() {
+            return is_retryable_sdk_error(e);
+        }
+        if let Some(e) = cause.downcast_ref::<SdkError<RetrieveAndGenerateStreamError>>() {
+            return is_retryable_sdk_error(e);
+        }
+        if let Some(e) = cause
+            .downcast_ref::<SdkError<RetrieveAndGenerateStreamResponseOutputError, RawMessage>>()
+        {
+            return is_retryable_sdk_error(e);
+        }
+        if let Some(e) = cause.downcast_ref::<SlackHttpError>() {
+            return e.is_retryable();
+        }
+        if let Some(e) = cause.downcast_ref::<SlackApiError>() {
+            return e.is_retryable();
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout() || e.is_connect();
+        }
+        false
+    })
+}
+
+/// The Slack `error` the failure comes from, if any.
+pub fn slack_api_error(error: &anyhow::Error) -> Option<&SlackApiError> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<SlackApiError>())
+}
+
+fn is_retryable_sdk_error<E: ProvideErrorMetadata, R>(error: &SdkError<E, R>) -> bool {
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(e) => e
+            .err()
+            .code()
+            .is_some_and(|code| RETRYABLE_ERROR_CODES.contains(&code)),
+        _ => false,
+    }
+}
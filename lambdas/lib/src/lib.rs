@@ -1,2 +1,5 @@
+pub mod config;
 pub mod env_keys;
+pub mod error;
+pub mod secrets;
 pub mod service;
@@ -0,0 +1,200 @@
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::Client;
+use serde_json::Value;
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// https://docs.aws.amazon.com/secretsmanager/latest/userguide/retrieving-secrets_lambda.html
+// the AWS Parameters and Secrets Lambda Extension serves both Secrets Manager and Parameter Store on localhost.
+const EXTENSION_PORT_ENV: &str = "PARAMETERS_SECRETS_EXTENSION_HTTP_PORT";
+const DEFAULT_EXTENSION_PORT: &str = "2773";
+const SESSION_TOKEN_ENV: &str = "AWS_SESSION_TOKEN";
+const SESSION_TOKEN_HEADER: &str = "X-Aws-Parameters-Secrets-Token";
+const SECRETS_MANAGER_PATH: &str = "/secretsmanager/get";
+const PARAMETER_STORE_PATH: &str = "/systemsmanager/parameters/get";
+
+pub const DEFAULT_SECRETS_CACHE_TTL: Duration = Duration::from_secs(60 * 5);
+
+/// Where the value of a secret is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// The value itself, ex: a plain env var for local runs.
+    Value(String),
+    /// Name or ARN of a Secrets Manager secret, holding the value as plain text.
+    SecretsManager(String),
+    /// Name or ARN of a Parameter Store parameter, usually a `SecureString`.
+    ParameterStore(String),
+}
+
+impl SecretSource {
+    /// Parameter ARNs (`arn:<partition>:ssm:...`, in any partition) and names starting with `/`
+    /// are read from Parameter Store, anything else from Secrets Manager.
+    pub fn from_id(id: &str) -> Self {
+        let is_parameter_arn = id.starts_with("arn:") && id.split(':').nth(2) == Some("ssm");
+        if id.starts_with('/') || is_parameter_arn {
+            SecretSource::ParameterStore(id.to_owned())
+        } else {
+            SecretSource::SecretsManager(id.to_owned())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedValue {
+    value: String,
+    fetched_at: Instant,
+}
+
+/// A secret that is fetched on first use and fetched again once it is older than `ttl`,
+/// so that a rotated value is picked up without redeploying.
+#[derive(Debug, Clone)]
+pub struct Secret {
+    name: String,
+    source: Option<SecretSource>,
+    ttl: Duration,
+    client: Client,
+    cached: Arc<Mutex<Option<CachedValue>>>,
+}
+
+impl Secret {
+    /// `name` is only used in errors and logs.
+    pub fn new(name: &str, source: Option<SecretSource>, ttl: Duration) -> Self {
+        Self {
+            name: name.to_owned(),
+            source,
+            ttl,
+            client: Client::new(),
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.source.is_some()
+    }
+
+    pub async fn get(&self) -> Result<String> {
+        let source = match &self.source {
+            None => bail!("{} is not configured.", self.name),
+            Some(SecretSource::Value(value)) => return Ok(value.to_owned()),
+            Some(source) => source,
+        };
+
+        let cached = self.cached()?;
+        if let Some(cached) = &cached {
+            if cached.fetched_at.elapsed() < self.ttl {
+                return Ok(cached.value.to_owned());
+            }
+        }
+
+        match self.fetch(source).await {
+            Ok(value) => {
+                let mut cache = self
+                    .cached
+                    .lock()
+                    .map_err(|_| anyhow!("secret cache lock poisoned."))?;
+                *cache = Some(CachedValue {
+                    value: value.to_owned(),
+                    fetched_at: Instant::now(),
+                });
+                Ok(value)
+            }
+            // keep using the previous value rather than failing every request.
+            Err(error) => match cached {
+                Some(cached) => {
+                    println!(
+                        "error refreshing {}, using the cached value: {}",
+                        self.name, error
+                    );
+                    Ok(cached.value)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    fn cached(&self) -> Result<Option<CachedValue>> {
+        Ok(self
+            .cached
+            .lock()
+            .map_err(|_| anyhow!("secret cache lock poisoned."))?
+            .clone())
+    }
+
+    async fn fetch(&self, source: &SecretSource) -> Result<String> {
+        let port = env::var(EXTENSION_PORT_ENV).unwrap_or(DEFAULT_EXTENSION_PORT.to_owned());
+        let session_token = env::var(SESSION_TOKEN_ENV)
+            .with_context(|| format!("{} is not set, not running in Lambda?", SESSION_TOKEN_ENV))?;
+
+        let (path, query) = match source {
+            SecretSource::SecretsManager(id) => {
+                (SECRETS_MANAGER_PATH, vec![("secretId", id.as_str())])
+            }
+            SecretSource::ParameterStore(name) => (
+                PARAMETER_STORE_PATH,
+                vec![("name", name.as_str()), ("withDecryption", "true")],
+            ),
+            SecretSource::Value(value) => return Ok(value.to_owned()),
+        };
+
+        let response = self
+            .client
+            .get(format!("http://localhost:{}{}", port, path))
+            .query(&query)
+            .header(SESSION_TOKEN_HEADER, session_token)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body_string = response.text().await?;
+        if !status.is_success() {
+            bail!("error fetching {}: {} {}", self.name, status, body_string);
+        }
+
+        let body: Value = serde_json::from_str(&body_string)?;
+        let value = match source {
+            SecretSource::SecretsManager(_) => body["SecretString"].as_str(),
+            _ => body["Parameter"]["Value"].as_str(),
+        };
+
+        value
+            .map(|v| v.to_owned())
+            .with_context(|| format!("{} has no string value.", self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_are_read_from_parameter_store_in_any_partition() {
+        for id in [
+            "/slack/bot-token",
+            "arn:aws:ssm:us-east-1:123456789012:parameter/slack/bot-token",
+            "arn:aws-cn:ssm:cn-north-1:123456789012:parameter/slack/bot-token",
+            "arn:aws-us-gov:ssm:us-gov-west-1:123456789012:parameter/slack/bot-token",
+        ] {
+            assert_eq!(
+                SecretSource::from_id(id),
+                SecretSource::ParameterStore(id.to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn anything_else_is_read_from_secrets_manager() {
+        for id in [
+            "slack-bot-token",
+            "arn:aws:secretsmanager:us-east-1:123456789012:secret:slack-bot-token-AbCdEf",
+            "arn:aws-cn:secretsmanager:cn-north-1:123456789012:secret:ssm-AbCdEf",
+        ] {
+            assert_eq!(
+                SecretSource::from_id(id),
+                SecretSource::SecretsManager(id.to_owned())
+            );
+        }
+    }
+}
@@ -0,0 +1,1357 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use aws_sdk_bedrockagent::{
+    error::DisplayErrorContext,
+    operation::list_data_sources::{ListDataSourcesError, ListDataSourcesOutput},
+    types::{
+        ContentDataSourceType, CustomContent, CustomDocumentIdentifier, CustomSourceType,
+        DataSourceSummary, DataSourceType, DocumentContent, DocumentIdentifier, DocumentMetadata,
+        DocumentStatus, IngestionJob, IngestionJobSortBy, IngestionJobSortByAttribute,
+        IngestionJobSummary, InlineContent, InlineContentType, KnowledgeBaseDocument,
+        KnowledgeBaseDocumentDetail, MetadataAttribute, MetadataAttributeValue, MetadataSourceType,
+        MetadataValueType, SortOrder, TextContentDoc,
+    },
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    sync_selection::{SyncFilter, SyncSelection},
+    BedrockService,
+};
+use crate::config::Config;
+
+// only one job of a data source runs at a time, so it is among the latest ones.
+const RECENT_INGESTION_JOBS: i32 = 5;
+
+// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_agent_IngestKnowledgeBaseDocuments.html
+const MAX_DOCUMENTS_PER_REQUEST: usize = 10;
+// one of the keys the citations take the title from.
+const TITLE_METADATA_KEY: &str = "title";
+
+// `detail-type` of the events of the EventBridge schedules.
+const SCHEDULED_EVENT_TYPE: &str = "Scheduled Event";
+
+/// Keeps the knowledge base up to date with its data sources.
+#[async_trait]
+pub trait KnowledgeBaseSync: Debug + Send + Sync {
+    /// Start an ingestion job for the data sources of the knowledge base in the `selection`.
+    /// A data source whose job cannot be started is reported as [`IngestionJobStatus::NotStarted`],
+    /// and one with a job already running is handled according to the [`SyncConflictPolicy`].
+    async fn start_data_sync(&self, selection: &SyncSelection) -> Result<Vec<IngestionJobReport>>;
+
+    /// The latest state of a started job.
+    /// Once a job with [`IngestionJobReport::start_when_finished`] finishes, the new job is started and returned instead.
+    async fn refresh_ingestion_job(&self, job: &IngestionJobReport) -> Result<IngestionJobReport>;
+
+    /// The data source of the knowledge base with this ID or name.
+    async fn data_source(&self, id_or_name: &str) -> Result<DataSourceInfo>;
+
+    /// Add or replace the `documents` of a custom data source and delete the ones with the `deleted` IDs,
+    /// leaving the rest of it as is. Bedrock indexes them in the background,
+    /// so the report is of the documents it accepted, without any job to refresh.
+    async fn sync_documents(
+        &self,
+        data_source: &DataSourceInfo,
+        documents: &[CustomDocument],
+        deleted: &[String],
+    ) -> Result<IngestionJobReport>;
+}
+
+#[async_trait]
+impl KnowledgeBaseSync for BedrockService {
+    async fn start_data_sync(&self, selection: &SyncSelection) -> Result<Vec<IngestionJobReport>> {
+        let summaries = self.data_source_summaries().await?;
+        let summaries = selection.select(summaries, |s| (s.data_source_id(), s.name()))?;
+        let datasource_ids: Vec<String> = summaries
+            .iter()
+            .map(|s| s.data_source_id().to_owned())
+            .collect();
+
+        println!("syncing data soucres: {:?}", datasource_ids);
+
+        let mut jobs = vec![];
+        for summary in summaries {
+            let id = summary.data_source_id();
+            // if the running job cannot be known, try starting anyway.
+            let running_job = match self.running_ingestion_job(id).await {
+                Ok(job) => job,
+                Err(error) => {
+                    println!("Error listing ingestion jobs of {}: {}", id, error);
+                    None
+                }
+            };
+
+            let job = match running_job {
+                None => self.start_ingestion_job(id, summary.name()).await,
+                Some(running_job) => {
+                    println!(
+                        "ingestion job {} of {} already running, policy: {:?}",
+                        running_job.ingestion_job_id(),
+                        id,
+                        self.sync_conflict_policy
+                    );
+                    self.resolve_conflict(summary.name(), &running_job).await
+                }
+            };
+            jobs.push(job);
+        }
+
+        Ok(jobs)
+    }
+
+    async fn refresh_ingestion_job(&self, job: &IngestionJobReport) -> Result<IngestionJobReport> {
+        let Some(ingestion_job_id) = &job.ingestion_job_id else {
+            return Ok(job.to_owned());
+        };
+
+        let output = self
+            .client
+            .get_ingestion_job()
+            .knowledge_base_id(self.knowledge_base_id()?)
+            .data_source_id(&job.data_source_id)
+            .ingestion_job_id(ingestion_job_id)
+            .send()
+            .await?;
+        let ingestion_job = output
+            .ingestion_job()
+            .with_context(|| format!("ingestion job {} not returned.", ingestion_job_id))?;
+        let mut report = ingestion_job_report(&job.data_source_name, ingestion_job);
+        report.conflict = job.conflict;
+        report.start_when_finished = job.start_when_finished;
+
+        // the job that was running is done, now for ours.
+        if report.start_when_finished && report.status.is_finished() {
+            let mut started = self
+                .start_ingestion_job(&job.data_source_id, &job.data_source_name)
+                .await;
+            started.conflict = job.conflict;
+            return Ok(started);
+        }
+
+        Ok(report)
+    }
+
+    async fn data_source(&self, id_or_name: &str) -> Result<DataSourceInfo> {
+        let summaries = self.data_source_summaries().await?;
+        let summary = summaries
+            .iter()
+            .find(|s| s.data_source_id() == id_or_name || s.name().eq_ignore_ascii_case(id_or_name))
+            .with_context(|| format!("no data source {} in the knowledge base.", id_or_name))?;
+
+        let output = self
+            .client
+            .get_data_source()
+            .knowledge_base_id(self.knowledge_base_id()?)
+            .data_source_id(summary.data_source_id())
+            .send()
+            .await
+            .map_err(|error| anyhow::anyhow!(DisplayErrorContext(&error).to_string()))?;
+        let custom = output
+            .data_source()
+            .and_then(|d| d.data_source_configuration())
+            .is_some_and(|c| c.r#type() == &DataSourceType::Custom);
+
+        Ok(DataSourceInfo {
+            id: summary.data_source_id().to_owned(),
+            name: summary.name().to_owned(),
+            custom,
+        })
+    }
+
+    async fn sync_documents(
+        &self,
+        data_source: &DataSourceInfo,
+        documents: &[CustomDocument],
+        deleted: &[String],
+    ) -> Result<IngestionJobReport> {
+        let knowledge_base_id = self.knowledge_base_id()?;
+        let mut details: Vec<KnowledgeBaseDocumentDetail> = vec![];
+
+        for documents in documents.chunks(MAX_DOCUMENTS_PER_REQUEST) {
+            let documents = documents
+                .iter()
+                .map(knowledge_base_document)
+                .collect::<Result<Vec<KnowledgeBaseDocument>>>()?;
+            let output = self
+                .client
+                .ingest_knowledge_base_documents()
+                .knowledge_base_id(knowledge_base_id)
+                .data_source_id(&data_source.id)
+                .set_documents(Some(documents))
+                .send()
+                .await
+                .map_err(|error| anyhow::anyhow!(DisplayErrorContext(&error).to_string()))?;
+            details.extend(output.document_details().to_vec());
+        }
+
+        for deleted in deleted.chunks(MAX_DOCUMENTS_PER_REQUEST) {
+            let identifiers = deleted
+                .iter()
+                .map(|id| custom_document_identifier(id))
+                .collect::<Result<Vec<DocumentIdentifier>>>()?;
+            let output = self
+                .client
+                .delete_knowledge_base_documents()
+                .knowledge_base_id(knowledge_base_id)
+                .data_source_id(&data_source.id)
+                .set_document_identifiers(Some(identifiers))
+                .send()
+                .await
+                .map_err(|error| anyhow::anyhow!(DisplayErrorContext(&error).to_string()))?;
+            details.extend(output.document_details().to_vec());
+        }
+
+        let failed: Vec<&KnowledgeBaseDocumentDetail> = details
+            .iter()
+            .filter(|d| d.status() == &DocumentStatus::Failed)
+            .collect();
+        let statistics = IngestionStatistics {
+            scanned: (documents.len() + deleted.len()) as i64,
+            modified_indexed: documents.len() as i64,
+            deleted: deleted.len() as i64,
+            failed: failed.len() as i64,
+            ..Default::default()
+        };
+        let failure_reasons = failed
+            .iter()
+            .map(|d| {
+                let id = d
+                    .identifier()
+                    .and_then(|i| i.custom())
+                    .map(|c| c.id())
+                    .unwrap_or("unknown document");
+                format!("{}: {}", id, d.status_reason().unwrap_or("failed"))
+            })
+            .collect();
+
+        Ok(IngestionJobReport::documents_synced(
+            data_source,
+            statistics,
+            failure_reasons,
+        ))
+    }
+}
+
+impl BedrockService {
+    async fn data_source_summaries(&self) -> Result<Vec<DataSourceSummary>> {
+        let knowledge_base_id = self.knowledge_base_id()?;
+        let datasource_stream = self
+            .client
+            .list_data_sources()
+            .knowledge_base_id(knowledge_base_id)
+            .into_paginator()
+            .send();
+        let results = datasource_stream
+            .collect::<Vec<
+                Result<
+                    ListDataSourcesOutput,
+                    aws_sdk_bedrockagent::error::SdkError<ListDataSourcesError, _>,
+                >,
+            >>()
+            .await;
+
+        let results: Result<Vec<ListDataSourcesOutput>, _> = results.into_iter().collect();
+
+        let summaries: Vec<DataSourceSummary> = match results {
+            Ok(r) => r
+                .iter()
+                .flat_map(|r| r.data_source_summaries().to_vec())
+                .collect(),
+            Err(error) => {
+                println!("Error getting datasource summary: {}, ", error);
+                bail!(error)
+            }
+        };
+
+        Ok(summaries)
+    }
+
+    async fn start_ingestion_job(
+        &self,
+        data_source_id: &str,
+        data_source_name: &str,
+    ) -> IngestionJobReport {
+        let knowledge_base_id = match self.knowledge_base_id() {
+            Ok(id) => id,
+            Err(error) => {
+                return IngestionJobReport::not_started(
+                    data_source_id,
+                    data_source_name,
+                    &error.to_string(),
+                )
+            }
+        };
+        let result = self
+            .client
+            .start_ingestion_job()
+            .knowledge_base_id(knowledge_base_id)
+            .data_source_id(data_source_id)
+            .send()
+            .await;
+
+        match result {
+            Ok(output) => match output.ingestion_job() {
+                Some(job) => ingestion_job_report(data_source_name, job),
+                None => IngestionJobReport::not_started(
+                    data_source_id,
+                    data_source_name,
+                    "no job returned.",
+                ),
+            },
+            Err(error) => {
+                let error = DisplayErrorContext(&error).to_string();
+                println!("Error starting data sync: {}", error);
+                IngestionJobReport::not_started(data_source_id, data_source_name, &error)
+            }
+        }
+    }
+
+    // the latest job of the data source, if it is not finished.
+    async fn running_ingestion_job(
+        &self,
+        data_source_id: &str,
+    ) -> Result<Option<IngestionJobSummary>> {
+        let output = self
+            .client
+            .list_ingestion_jobs()
+            .knowledge_base_id(self.knowledge_base_id()?)
+            .data_source_id(data_source_id)
+            .sort_by(
+                IngestionJobSortBy::builder()
+                    .attribute(IngestionJobSortByAttribute::StartedAt)
+                    .order(SortOrder::Descending)
+                    .build()?,
+            )
+            .max_results(RECENT_INGESTION_JOBS)
+            .send()
+            .await
+            .map_err(|error| anyhow::anyhow!(DisplayErrorContext(&error).to_string()))?;
+
+        Ok(output
+            .ingestion_job_summaries()
+            .iter()
+            .find(|job| !IngestionJobStatus::from_sdk(job.status()).is_finished())
+            .cloned())
+    }
+
+    async fn resolve_conflict(
+        &self,
+        data_source_name: &str,
+        running_job: &IngestionJobSummary,
+    ) -> IngestionJobReport {
+        let policy = self.sync_conflict_policy;
+        let report = ingestion_job_summary_report(data_source_name, running_job);
+
+        let stopped = match policy {
+            SyncConflictPolicy::Restart if report.status != IngestionJobStatus::Stopping => {
+                let result = self.stop_ingestion_job(running_job).await;
+                if let Err(error) = &result {
+                    println!(
+                        "Error stopping ingestion job {}: {}",
+                        running_job.ingestion_job_id(),
+                        error
+                    );
+                }
+                Some(result)
+            }
+            _ => None,
+        };
+
+        apply_conflict_policy(report, policy, stopped)
+    }
+
+    async fn stop_ingestion_job(&self, job: &IngestionJobSummary) -> Result<()> {
+        self.client
+            .stop_ingestion_job()
+            .knowledge_base_id(self.knowledge_base_id()?)
+            .data_source_id(job.data_source_id())
+            .ingestion_job_id(job.ingestion_job_id())
+            .send()
+            .await
+            .map_err(|error| anyhow::anyhow!(DisplayErrorContext(&error).to_string()))?;
+        Ok(())
+    }
+}
+
+// the report of the job already running once the `policy` is applied,
+// `stopped` being the result of stopping it to restart, if it was not already stopping.
+fn apply_conflict_policy(
+    mut report: IngestionJobReport,
+    policy: SyncConflictPolicy,
+    stopped: Option<Result<()>>,
+) -> IngestionJobReport {
+    report.conflict = Some(policy);
+
+    match policy {
+        SyncConflictPolicy::Skip => report.status = IngestionJobStatus::Skipped,
+        SyncConflictPolicy::Wait => report.start_when_finished = true,
+        SyncConflictPolicy::Restart => {
+            report.start_when_finished = true;
+            match stopped {
+                Some(Ok(())) => report.status = IngestionJobStatus::Stopping,
+                Some(Err(error)) => {
+                    // nothing was restarted, the reason says why.
+                    report.failure_reasons.push(format!(
+                        "could not stop the job already running to restart it: {}",
+                        error
+                    ));
+                    report.conflict = None;
+                    report.start_when_finished = false;
+                    report.status = IngestionJobStatus::NotStarted;
+                }
+                None => {}
+            }
+        }
+    }
+
+    report
+}
+
+// inline text, with the title as metadata for the citations.
+fn knowledge_base_document(document: &CustomDocument) -> Result<KnowledgeBaseDocument> {
+    let content = CustomContent::builder()
+        .custom_document_identifier(
+            CustomDocumentIdentifier::builder()
+                .id(&document.id)
+                .build()?,
+        )
+        .source_type(CustomSourceType::InLine)
+        .inline_content(
+            InlineContent::builder()
+                .r#type(InlineContentType::Text)
+                .text_content(TextContentDoc::builder().data(&document.text).build()?)
+                .build()?,
+        )
+        .build()?;
+    let mut builder = KnowledgeBaseDocument::builder().content(
+        DocumentContent::builder()
+            .data_source_type(ContentDataSourceType::Custom)
+            .custom(content)
+            .build()?,
+    );
+
+    if let Some(title) = &document.title {
+        let value = MetadataAttributeValue::builder()
+            .r#type(MetadataValueType::String)
+            .string_value(title)
+            .build()?;
+        builder = builder.metadata(
+            DocumentMetadata::builder()
+                .r#type(MetadataSourceType::InLineAttribute)
+                .inline_attributes(
+                    MetadataAttribute::builder()
+                        .key(TITLE_METADATA_KEY)
+                        .value(value)
+                        .build()?,
+                )
+                .build()?,
+        );
+    }
+
+    Ok(builder.build())
+}
+
+fn custom_document_identifier(id: &str) -> Result<DocumentIdentifier> {
+    Ok(DocumentIdentifier::builder()
+        .data_source_type(ContentDataSourceType::Custom)
+        .custom(CustomDocumentIdentifier::builder().id(id).build()?)
+        .build()?)
+}
+
+fn ingestion_job_report(data_source_name: &str, job: &IngestionJob) -> IngestionJobReport {
+    IngestionJobReport {
+        data_source_id: job.data_source_id().to_owned(),
+        data_source_name: data_source_name.to_owned(),
+        ingestion_job_id: Some(job.ingestion_job_id().to_owned()),
+        status: IngestionJobStatus::from_sdk(job.status()),
+        statistics: job.statistics().map(IngestionStatistics::from_sdk),
+        failure_reasons: job.failure_reasons().to_vec(),
+        started_at: Some(job.started_at().secs()),
+        updated_at: Some(job.updated_at().secs()),
+        conflict: None,
+        start_when_finished: false,
+    }
+}
+
+fn ingestion_job_summary_report(
+    data_source_name: &str,
+    job: &IngestionJobSummary,
+) -> IngestionJobReport {
+    IngestionJobReport {
+        data_source_id: job.data_source_id().to_owned(),
+        data_source_name: data_source_name.to_owned(),
+        ingestion_job_id: Some(job.ingestion_job_id().to_owned()),
+        status: IngestionJobStatus::from_sdk(job.status()),
+        statistics: job.statistics().map(IngestionStatistics::from_sdk),
+        failure_reasons: vec![],
+        started_at: Some(job.started_at().secs()),
+        updated_at: Some(job.updated_at().secs()),
+        conflict: None,
+        start_when_finished: false,
+    }
+}
+
+/// A data source of the knowledge base.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DataSourceInfo {
+    pub id: String,
+    pub name: String,
+    /// Its documents are given to Bedrock with [`KnowledgeBaseSync::sync_documents`] rather than crawled by an ingestion job.
+    pub custom: bool,
+}
+
+/// A document of a custom data source.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CustomDocument {
+    /// Unique in the data source, ex: the URL of a page.
+    pub id: String,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// What to do when a data source already has an ingestion job running, ex: a manual sync.
+/// Bedrock rejects a second job with a `ConflictException`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncConflictPolicy {
+    /// Leave the data source to the running job.
+    #[default]
+    Skip,
+    /// Start a new job once the running one finishes.
+    Wait,
+    /// Stop the running job and start a new one once it is stopped.
+    Restart,
+}
+
+impl SyncConflictPolicy {
+    /// `skip`, `wait` or `restart`, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "skip" => Some(SyncConflictPolicy::Skip),
+            "wait" => Some(SyncConflictPolicy::Wait),
+            "restart" => Some(SyncConflictPolicy::Restart),
+            _ => None,
+        }
+    }
+}
+
+/// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_agent_IngestionJob.html
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IngestionJobStatus {
+    Starting,
+    InProgress,
+    Stopping,
+    Complete,
+    Failed,
+    Stopped,
+    /// `start_ingestion_job` failed.
+    NotStarted,
+    /// Another job of the data source was running, see [`SyncConflictPolicy::Skip`].
+    Skipped,
+    /// Added to Bedrock after this was written, treated as still running.
+    Unknown,
+}
+
+impl IngestionJobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            IngestionJobStatus::Complete
+                | IngestionJobStatus::Failed
+                | IngestionJobStatus::Stopped
+                | IngestionJobStatus::NotStarted
+                | IngestionJobStatus::Skipped
+        )
+    }
+
+    pub fn from_sdk(status: &aws_sdk_bedrockagent::types::IngestionJobStatus) -> Self {
+        use aws_sdk_bedrockagent::types::IngestionJobStatus as Status;
+        match status {
+            Status::Starting => IngestionJobStatus::Starting,
+            Status::InProgress => IngestionJobStatus::InProgress,
+            Status::Stopping => IngestionJobStatus::Stopping,
+            Status::Complete => IngestionJobStatus::Complete,
+            Status::Failed => IngestionJobStatus::Failed,
+            Status::Stopped => IngestionJobStatus::Stopped,
+            _ => IngestionJobStatus::Unknown,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            IngestionJobStatus::Starting => "starting",
+            IngestionJobStatus::InProgress => "in progress",
+            IngestionJobStatus::Stopping => "stopping",
+            IngestionJobStatus::Complete => "complete",
+            IngestionJobStatus::Failed => "failed",
+            IngestionJobStatus::Stopped => "stopped",
+            IngestionJobStatus::NotStarted => "not started",
+            IngestionJobStatus::Skipped => "skipped",
+            IngestionJobStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// Document counts of an ingestion job.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestionStatistics {
+    pub scanned: i64,
+    pub new_indexed: i64,
+    pub modified_indexed: i64,
+    pub deleted: i64,
+    pub failed: i64,
+    pub metadata_scanned: i64,
+    pub metadata_modified: i64,
+}
+
+impl IngestionStatistics {
+    pub fn from_sdk(statistics: &aws_sdk_bedrockagent::types::IngestionJobStatistics) -> Self {
+        Self {
+            scanned: statistics.number_of_documents_scanned(),
+            new_indexed: statistics.number_of_new_documents_indexed(),
+            modified_indexed: statistics.number_of_modified_documents_indexed(),
+            deleted: statistics.number_of_documents_deleted(),
+            failed: statistics.number_of_documents_failed(),
+            metadata_scanned: statistics.number_of_metadata_documents_scanned(),
+            metadata_modified: statistics.number_of_metadata_documents_modified(),
+        }
+    }
+}
+
+/// The ingestion job of one data source.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IngestionJobReport {
+    pub data_source_id: String,
+    pub data_source_name: String,
+    /// `None` if the job could not be started.
+    pub ingestion_job_id: Option<String>,
+    pub status: IngestionJobStatus,
+    pub statistics: Option<IngestionStatistics>,
+    /// Why the job failed, or could not be started.
+    pub failure_reasons: Vec<String>,
+    /// Epoch seconds.
+    pub started_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    /// The policy applied because a job of the data source was already running, if one was.
+    #[serde(default)]
+    pub conflict: Option<SyncConflictPolicy>,
+    /// This is the job that was already running, and a new one is to be started once it finishes.
+    #[serde(default)]
+    pub start_when_finished: bool,
+}
+
+impl IngestionJobReport {
+    /// A data source whose job could not be started because of `error`.
+    pub fn not_started(data_source_id: &str, data_source_name: &str, error: &str) -> Self {
+        Self {
+            data_source_id: data_source_id.to_owned(),
+            data_source_name: data_source_name.to_owned(),
+            ingestion_job_id: None,
+            status: IngestionJobStatus::NotStarted,
+            statistics: None,
+            failure_reasons: vec![error.to_owned()],
+            started_at: None,
+            updated_at: None,
+            conflict: None,
+            start_when_finished: false,
+        }
+    }
+
+    /// The documents given to a custom data source, failed if Bedrock rejected any of them.
+    pub fn documents_synced(
+        data_source: &DataSourceInfo,
+        statistics: IngestionStatistics,
+        failure_reasons: Vec<String>,
+    ) -> Self {
+        let status = if statistics.failed > 0 {
+            IngestionJobStatus::Failed
+        } else {
+            IngestionJobStatus::Complete
+        };
+        Self {
+            data_source_id: data_source.id.to_owned(),
+            data_source_name: data_source.name.to_owned(),
+            ingestion_job_id: None,
+            status,
+            statistics: Some(statistics),
+            failure_reasons,
+            started_at: None,
+            updated_at: None,
+            conflict: None,
+            start_when_finished: false,
+        }
+    }
+
+    /// Still running, or waiting for the running job to finish to start a new one.
+    pub fn is_finished(&self) -> bool {
+        self.status.is_finished() && !self.start_when_finished
+    }
+
+    /// From the start to the last update of the job.
+    pub fn duration(&self) -> Option<Duration> {
+        match (self.started_at, self.updated_at) {
+            (Some(start), Some(end)) if end >= start => {
+                Some(Duration::from_secs((end - start) as u64))
+            }
+            _ => None,
+        }
+    }
+
+    // `*name*: complete in 3m 20s`, the counts and the failure reasons.
+    fn summary(&self) -> String {
+        let mut lines = vec![];
+
+        let mut heading = format!("*{}*: {}", self.data_source_name, self.status.label());
+        if let Some(duration) = self.duration().filter(|_| self.status.is_finished()) {
+            heading.push_str(&format!(" in {}", format_duration(duration)));
+        }
+        match (self.conflict, self.start_when_finished) {
+            (None, _) => {}
+            (Some(SyncConflictPolicy::Skip), _) => {
+                heading.push_str(" (a job was already running, left to it)")
+            }
+            (Some(SyncConflictPolicy::Wait), true) => {
+                heading.push_str(" (waiting for the job already running to finish)")
+            }
+            (Some(SyncConflictPolicy::Wait), false) => {
+                heading.push_str(" (waited for the job already running)")
+            }
+            (Some(SyncConflictPolicy::Restart), true) => {
+                heading.push_str(" (stopping the job already running to restart)")
+            }
+            (Some(SyncConflictPolicy::Restart), false) => {
+                heading.push_str(" (restarted, the job already running was stopped)")
+            }
+        }
+        lines.push(heading);
+
+        if let Some(s) = &self.statistics {
+            lines.push(format!(
+                "scanned {}, indexed {} new and {} modified, deleted {}, failed {}",
+                s.scanned, s.new_indexed, s.modified_indexed, s.deleted, s.failed
+            ));
+        }
+        for reason in &self.failure_reasons {
+            lines.push(format!("• {}", reason));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Input of the sync state machine, ex: `{"data_sources": ["engineering"]}` when started on demand.
+/// EventBridge events give the same fields in their `detail` instead,
+/// and the data sources are left to the [`Config`] if neither does.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SyncRequest {
+    #[serde(flatten)]
+    pub filter: SyncFilter,
+    /// Where to post the progress, for a sync requested from Slack.
+    #[serde(default)]
+    pub reply: Option<SyncReply>,
+    /// Of EventBridge events, ex: `{"exclude": ["archive-*"]}` in the input of a rule target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<SyncFilter>,
+    #[serde(
+        default,
+        rename = "detail-type",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub detail_type: Option<String>,
+    /// When the event was sent, RFC 3339.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// Confluence page IDs, to sync only these pages of the one custom data source in `data_sources`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<String>,
+    /// When the sync state machine starts the sync, RFC 3339, ex: at the end of the debounce window of a webhook.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_at: Option<String>,
+}
+
+impl SyncRequest {
+    /// The data sources to sync, and for the scheduled syncs, only the ones due by their frequency.
+    pub fn selection(&self, config: &Config) -> Result<SyncSelection> {
+        let filter = [Some(&self.filter), self.detail.as_ref()]
+            .into_iter()
+            .flatten()
+            .find(|filter| !filter.is_empty())
+            .unwrap_or(&config.sync_filter);
+
+        let scheduled_at = match (self.detail_type.as_deref(), &self.time) {
+            (Some(SCHEDULED_EVENT_TYPE), Some(time)) => Some(
+                DateTime::parse_from_rfc3339(time)
+                    .with_context(|| format!("time `{}` of the event is not RFC 3339.", time))?
+                    .with_timezone(&Utc),
+            ),
+            _ => None,
+        };
+
+        Ok(SyncSelection {
+            data_sources: filter.data_sources.to_owned(),
+            include: filter.include.to_owned(),
+            exclude: filter.exclude.to_owned(),
+            scheduled_at,
+            frequencies: config.sync_frequencies.to_owned(),
+        })
+    }
+}
+
+/// The Slack conversation a sync was requested from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SyncReply {
+    pub channel_id: String,
+    pub thread_ts: Option<String>,
+    /// Who to mention once the sync finishes.
+    pub user_id: Option<String>,
+    /// The message showing the progress, once posted.
+    #[serde(default)]
+    pub message_ts: Option<String>,
+}
+
+/// The ingestion jobs of one sync, checked on until all of them finish.
+///
+/// Returned by the sync lambda and passed back to it as is by the state machine polling it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DataSyncRun {
+    pub jobs: Vec<IngestionJobReport>,
+    pub finished: bool,
+    #[serde(default)]
+    pub reply: Option<SyncReply>,
+}
+
+impl DataSyncRun {
+    pub fn new(jobs: Vec<IngestionJobReport>) -> Self {
+        let finished = jobs.iter().all(|job| job.is_finished());
+        Self {
+            jobs,
+            finished,
+            reply: None,
+        }
+    }
+
+    /// Get the latest state of the jobs still running.
+    /// A job that cannot be checked is tried again on the next refresh.
+    pub async fn refresh(&mut self, sync: &dyn KnowledgeBaseSync) {
+        for job in self.jobs.iter_mut() {
+            if job.is_finished() {
+                continue;
+            }
+            match sync.refresh_ingestion_job(job).await {
+                Ok(refreshed) => *job = refreshed,
+                Err(error) => println!(
+                    "error checking ingestion job {:?} of {}: {}",
+                    job.ingestion_job_id, job.data_source_id, error
+                ),
+            }
+        }
+        self.finished = self.jobs.iter().all(|job| job.is_finished());
+    }
+
+    /// The outcome of each job, for the logs and the ops channel.
+    pub fn summary(&self) -> String {
+        let count = |status: IngestionJobStatus| {
+            self.jobs.iter().filter(|job| job.status == status).count()
+        };
+        let heading = if self.jobs.is_empty() {
+            "Knowledge base sync: no data source to sync.".to_owned()
+        } else {
+            format!(
+                "Knowledge base sync {}: {} complete, {} failed, {} stopped, {} not started, {} skipped.",
+                if self.finished { "finished" } else { "running" },
+                count(IngestionJobStatus::Complete),
+                count(IngestionJobStatus::Failed),
+                count(IngestionJobStatus::Stopped),
+                count(IngestionJobStatus::NotStarted),
+                count(IngestionJobStatus::Skipped),
+            )
+        };
+
+        std::iter::once(heading)
+            .chain(self.jobs.iter().map(|job| job.summary()))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+// `1h 2m 3s`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+/// Counts the syncs instead of starting them, for tests and local runs.
+/// Each sync reports a job per data source given to [`InMemoryKnowledgeBaseSync::with_data_sources`]
+/// and selected, which completes on its first refresh.
+/// The ones given to [`InMemoryKnowledgeBaseSync::with_running_jobs`] report their running job instead,
+/// handled by the [`SyncConflictPolicy`].
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryKnowledgeBaseSync {
+    sync_count: Arc<Mutex<usize>>,
+    data_sources: Vec<String>,
+    custom_data_sources: Vec<String>,
+    running_jobs: Vec<String>,
+    sync_conflict_policy: SyncConflictPolicy,
+    documents: Arc<Mutex<Vec<CustomDocument>>>,
+}
+
+impl InMemoryKnowledgeBaseSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Data sources by name, their IDs being the same.
+    pub fn with_data_sources(data_sources: &[&str]) -> Self {
+        Self {
+            data_sources: data_sources.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Custom data sources by name, in addition to the ones above.
+    pub fn with_custom_data_sources(mut self, data_sources: &[&str]) -> Self {
+        self.custom_data_sources = data_sources.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Data sources by name, with a job already running on every sync, handled by the `policy`.
+    pub fn with_running_jobs(mut self, data_sources: &[&str], policy: SyncConflictPolicy) -> Self {
+        self.running_jobs = data_sources.iter().map(|s| s.to_string()).collect();
+        self.sync_conflict_policy = policy;
+        self
+    }
+
+    /// The documents of the custom data sources, as last synced.
+    pub fn documents(&self) -> Vec<CustomDocument> {
+        self.documents
+            .lock()
+            .map(|documents| documents.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn sync_count(&self) -> usize {
+        self.sync_count
+            .lock()
+            .map(|count| *count)
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl KnowledgeBaseSync for InMemoryKnowledgeBaseSync {
+    async fn start_data_sync(&self, selection: &SyncSelection) -> Result<Vec<IngestionJobReport>> {
+        let all = [
+            self.data_sources.to_owned(),
+            self.custom_data_sources.to_owned(),
+        ]
+        .concat();
+        let data_sources = selection.select(all, |name| (name, name))?;
+        let mut count = self
+            .sync_count
+            .lock()
+            .map_err(|_| anyhow!("knowledge base sync lock poisoned."))?;
+        *count += 1;
+
+        Ok(data_sources
+            .iter()
+            .map(|name| {
+                if !self.running_jobs.contains(name) {
+                    return in_memory_job(name, &format!("{}-{}", name, count));
+                }
+                let running_job = IngestionJobReport {
+                    status: IngestionJobStatus::InProgress,
+                    ..in_memory_job(name, &format!("{}-running", name))
+                };
+                // stopping always works.
+                let stopped =
+                    (self.sync_conflict_policy == SyncConflictPolicy::Restart).then_some(Ok(()));
+                apply_conflict_policy(running_job, self.sync_conflict_policy, stopped)
+            })
+            .collect())
+    }
+
+    async fn refresh_ingestion_job(&self, job: &IngestionJobReport) -> Result<IngestionJobReport> {
+        // the running job finishes on its first refresh, and ours starts in its place.
+        if job.start_when_finished {
+            let count = self.sync_count();
+            return Ok(IngestionJobReport {
+                conflict: job.conflict,
+                ..in_memory_job(
+                    &job.data_source_name,
+                    &format!("{}-{}", job.data_source_name, count),
+                )
+            });
+        }
+
+        let status = match job.status {
+            IngestionJobStatus::Stopping => IngestionJobStatus::Stopped,
+            _ => IngestionJobStatus::Complete,
+        };
+        Ok(IngestionJobReport {
+            status,
+            statistics: Some(IngestionStatistics::default()),
+            ..job.to_owned()
+        })
+    }
+
+    async fn data_source(&self, id_or_name: &str) -> Result<DataSourceInfo> {
+        let custom = self
+            .custom_data_sources
+            .iter()
+            .any(|name| name == id_or_name);
+        if !custom && !self.data_sources.iter().any(|name| name == id_or_name) {
+            bail!("no data source {} in the knowledge base.", id_or_name);
+        }
+        Ok(DataSourceInfo {
+            id: id_or_name.to_owned(),
+            name: id_or_name.to_owned(),
+            custom,
+        })
+    }
+
+    async fn sync_documents(
+        &self,
+        data_source: &DataSourceInfo,
+        documents: &[CustomDocument],
+        deleted: &[String],
+    ) -> Result<IngestionJobReport> {
+        let mut count = self
+            .sync_count
+            .lock()
+            .map_err(|_| anyhow!("knowledge base sync lock poisoned."))?;
+        *count += 1;
+        let mut synced = self
+            .documents
+            .lock()
+            .map_err(|_| anyhow!("knowledge base sync lock poisoned."))?;
+        synced.retain(|synced| {
+            !deleted.contains(&synced.id) && !documents.iter().any(|d| d.id == synced.id)
+        });
+        synced.extend(documents.iter().cloned());
+
+        let statistics = IngestionStatistics {
+            scanned: (documents.len() + deleted.len()) as i64,
+            modified_indexed: documents.len() as i64,
+            deleted: deleted.len() as i64,
+            ..Default::default()
+        };
+        Ok(IngestionJobReport::documents_synced(
+            data_source,
+            statistics,
+            vec![],
+        ))
+    }
+}
+
+// a job of the in-memory knowledge base, just started.
+fn in_memory_job(data_source_name: &str, ingestion_job_id: &str) -> IngestionJobReport {
+    IngestionJobReport {
+        data_source_id: data_source_name.to_owned(),
+        data_source_name: data_source_name.to_owned(),
+        ingestion_job_id: Some(ingestion_job_id.to_owned()),
+        status: IngestionJobStatus::Starting,
+        statistics: None,
+        failure_reasons: vec![],
+        started_at: None,
+        updated_at: None,
+        conflict: None,
+        start_when_finished: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(status: IngestionJobStatus) -> IngestionJobReport {
+        IngestionJobReport {
+            data_source_id: "ds-1".to_owned(),
+            data_source_name: "engineering".to_owned(),
+            ingestion_job_id: Some("job-1".to_owned()),
+            status,
+            statistics: None,
+            failure_reasons: vec![],
+            started_at: Some(1_000),
+            updated_at: Some(1_200),
+            conflict: None,
+            start_when_finished: false,
+        }
+    }
+
+    #[test]
+    fn finished_statuses() {
+        use IngestionJobStatus::*;
+        for status in [Complete, Failed, Stopped, NotStarted, Skipped] {
+            assert!(status.is_finished(), "{:?}", status);
+        }
+        for status in [Starting, InProgress, Stopping, Unknown] {
+            assert!(!status.is_finished(), "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn report_waiting_to_start_a_job_is_not_finished() {
+        let mut waiting = report(IngestionJobStatus::Complete);
+        assert!(waiting.is_finished());
+
+        waiting.start_when_finished = true;
+        assert!(!waiting.is_finished());
+    }
+
+    #[test]
+    fn statuses_from_the_sdk() {
+        use aws_sdk_bedrockagent::types::IngestionJobStatus as Status;
+        let cases = [
+            (Status::Starting, IngestionJobStatus::Starting),
+            (Status::InProgress, IngestionJobStatus::InProgress),
+            (Status::Stopping, IngestionJobStatus::Stopping),
+            (Status::Complete, IngestionJobStatus::Complete),
+            (Status::Failed, IngestionJobStatus::Failed),
+            (Status::Stopped, IngestionJobStatus::Stopped),
+            (Status::from("QUEUED"), IngestionJobStatus::Unknown),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(IngestionJobStatus::from_sdk(&status), expected);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3_723)), "1h 2m 3s");
+    }
+
+    #[test]
+    fn duration_of_a_report() {
+        assert_eq!(
+            report(IngestionJobStatus::Complete).duration(),
+            Some(Duration::from_secs(200))
+        );
+
+        let mut unordered = report(IngestionJobStatus::Complete);
+        unordered.updated_at = Some(0);
+        assert_eq!(unordered.duration(), None);
+        assert_eq!(
+            IngestionJobReport::not_started("ds-1", "engineering", "denied").duration(),
+            None
+        );
+    }
+
+    #[test]
+    fn summary_of_each_status() {
+        use IngestionJobStatus::*;
+        let cases = [
+            (Starting, "*engineering*: starting"),
+            (InProgress, "*engineering*: in progress"),
+            (Stopping, "*engineering*: stopping"),
+            (Unknown, "*engineering*: unknown"),
+            (Complete, "*engineering*: complete in 3m 20s"),
+            (Failed, "*engineering*: failed in 3m 20s"),
+            (Stopped, "*engineering*: stopped in 3m 20s"),
+            (Skipped, "*engineering*: skipped in 3m 20s"),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(report(status).summary(), expected);
+        }
+    }
+
+    #[test]
+    fn summary_with_statistics_and_failure_reasons() {
+        let mut failed = report(IngestionJobStatus::Failed);
+        failed.statistics = Some(IngestionStatistics {
+            scanned: 10,
+            new_indexed: 2,
+            modified_indexed: 3,
+            deleted: 1,
+            failed: 4,
+            ..Default::default()
+        });
+        failed.failure_reasons = vec!["too large".to_owned(), "unsupported".to_owned()];
+
+        assert_eq!(
+            failed.summary(),
+            "*engineering*: failed in 3m 20s\n\
+             scanned 10, indexed 2 new and 3 modified, deleted 1, failed 4\n\
+             • too large\n\
+             • unsupported"
+        );
+        assert_eq!(
+            IngestionJobReport::not_started("ds-1", "engineering", "access denied").summary(),
+            "*engineering*: not started\n• access denied"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_refreshes_the_jobs_until_finished() {
+        let sync = InMemoryKnowledgeBaseSync::with_data_sources(&["engineering", "hr"]);
+        let mut jobs = sync
+            .start_data_sync(&SyncSelection::default())
+            .await
+            .unwrap();
+        jobs.push(IngestionJobReport::not_started("ds-3", "sales", "denied"));
+
+        let mut run = DataSyncRun::new(jobs);
+        assert!(!run.finished);
+        assert_eq!(
+            run.summary(),
+            "Knowledge base sync running: 0 complete, 0 failed, 0 stopped, 1 not started, 0 skipped.\n\n\
+             *engineering*: starting\n\n\
+             *hr*: starting\n\n\
+             *sales*: not started\n• denied"
+        );
+
+        run.refresh(&sync).await;
+        assert!(run.finished);
+        assert_eq!(run.jobs[2].status, IngestionJobStatus::NotStarted);
+        assert_eq!(
+            run.summary(),
+            "Knowledge base sync finished: 2 complete, 0 failed, 0 stopped, 1 not started, 0 skipped.\n\n\
+             *engineering*: complete\n\
+             scanned 0, indexed 0 new and 0 modified, deleted 0, failed 0\n\n\
+             *hr*: complete\n\
+             scanned 0, indexed 0 new and 0 modified, deleted 0, failed 0\n\n\
+             *sales*: not started\n• denied"
+        );
+    }
+
+    #[tokio::test]
+    async fn running_job_is_skipped() {
+        let sync = InMemoryKnowledgeBaseSync::with_data_sources(&["engineering", "hr"])
+            .with_running_jobs(&["engineering"], SyncConflictPolicy::Skip);
+        let jobs = sync
+            .start_data_sync(&SyncSelection::default())
+            .await
+            .unwrap();
+        let run = DataSyncRun::new(jobs);
+
+        let job = &run.jobs[0];
+        assert_eq!(job.ingestion_job_id.as_deref(), Some("engineering-running"));
+        assert_eq!(job.status, IngestionJobStatus::Skipped);
+        assert_eq!(job.conflict, Some(SyncConflictPolicy::Skip));
+        assert!(job.is_finished());
+        assert_eq!(
+            job.summary(),
+            "*engineering*: skipped (a job was already running, left to it)"
+        );
+        assert_eq!(run.jobs[1].status, IngestionJobStatus::Starting);
+    }
+
+    #[tokio::test]
+    async fn new_job_starts_once_the_running_one_finishes() {
+        let sync = InMemoryKnowledgeBaseSync::with_data_sources(&["engineering", "hr"])
+            .with_running_jobs(&["engineering"], SyncConflictPolicy::Wait);
+        let jobs = sync
+            .start_data_sync(&SyncSelection::default())
+            .await
+            .unwrap();
+        let mut run = DataSyncRun::new(jobs);
+
+        let job = &run.jobs[0];
+        assert_eq!(job.status, IngestionJobStatus::InProgress);
+        assert!(job.start_when_finished);
+        assert!(!run.finished);
+        assert_eq!(
+            job.summary(),
+            "*engineering*: in progress (waiting for the job already running to finish)"
+        );
+
+        // the running job finished, ours started.
+        run.refresh(&sync).await;
+        let job = &run.jobs[0];
+        assert_eq!(job.ingestion_job_id.as_deref(), Some("engineering-1"));
+        assert_eq!(job.status, IngestionJobStatus::Starting);
+        assert_eq!(job.conflict, Some(SyncConflictPolicy::Wait));
+        assert!(!job.start_when_finished);
+        assert!(!run.finished);
+
+        run.refresh(&sync).await;
+        let job = &run.jobs[0];
+        assert_eq!(job.status, IngestionJobStatus::Complete);
+        assert_eq!(job.conflict, Some(SyncConflictPolicy::Wait));
+        assert!(run.finished);
+        assert!(job
+            .summary()
+            .starts_with("*engineering*: complete (waited for the job already running)"));
+    }
+
+    #[tokio::test]
+    async fn running_job_is_stopped_to_restart() {
+        let sync = InMemoryKnowledgeBaseSync::with_data_sources(&["engineering"])
+            .with_running_jobs(&["engineering"], SyncConflictPolicy::Restart);
+        let jobs = sync
+            .start_data_sync(&SyncSelection::default())
+            .await
+            .unwrap();
+        let mut run = DataSyncRun::new(jobs);
+
+        let job = &run.jobs[0];
+        assert_eq!(job.status, IngestionJobStatus::Stopping);
+        assert!(job.start_when_finished);
+        assert_eq!(
+            job.summary(),
+            "*engineering*: stopping (stopping the job already running to restart)"
+        );
+
+        run.refresh(&sync).await;
+        let job = &run.jobs[0];
+        assert_eq!(job.ingestion_job_id.as_deref(), Some("engineering-1"));
+        assert_eq!(job.conflict, Some(SyncConflictPolicy::Restart));
+
+        run.refresh(&sync).await;
+        assert!(run.finished);
+        assert!(run.jobs[0].summary().starts_with(
+            "*engineering*: complete (restarted, the job already running was stopped)"
+        ));
+    }
+
+    #[test]
+    fn job_already_stopping_is_not_stopped_again() {
+        let stopping = report(IngestionJobStatus::Stopping);
+        let job = apply_conflict_policy(stopping, SyncConflictPolicy::Restart, None);
+
+        assert_eq!(job.status, IngestionJobStatus::Stopping);
+        assert_eq!(job.conflict, Some(SyncConflictPolicy::Restart));
+        assert!(job.start_when_finished);
+    }
+
+    #[test]
+    fn job_that_cannot_be_stopped_is_not_restarted() {
+        let running = report(IngestionJobStatus::InProgress);
+        let job = apply_conflict_policy(
+            running,
+            SyncConflictPolicy::Restart,
+            Some(Err(anyhow!("access denied"))),
+        );
+
+        assert_eq!(job.status, IngestionJobStatus::NotStarted);
+        assert_eq!(job.conflict, None);
+        assert!(job.is_finished());
+        assert_eq!(
+            job.summary(),
+            "*engineering*: not started in 3m 20s\n\
+             • could not stop the job already running to restart it: access denied"
+        );
+    }
+
+    #[test]
+    fn run_without_jobs_is_finished() {
+        let run = DataSyncRun::new(vec![]);
+        assert!(run.finished);
+        assert_eq!(
+            run.summary(),
+            "Knowledge base sync: no data source to sync."
+        );
+    }
+}
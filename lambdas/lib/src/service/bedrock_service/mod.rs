@@ -1,96 +1,280 @@
-use anyhow::{bail, Result};
-use aws_sdk_bedrockagent::{
-    operation::list_data_sources::{ListDataSourcesError, ListDataSourcesOutput},
-    types::DataSourceSummary,
-};
-use aws_sdk_bedrockagentruntime::types::{
-    KnowledgeBaseRetrieveAndGenerateConfiguration, RetrievalResultLocationType,
-    RetrieveAndGenerateConfiguration, RetrieveAndGenerateInput,
+use anyhow::{bail, Context, Result};
+use aws_sdk_bedrockagentruntime::{
+    error::{ProvideErrorMetadata, SdkError},
+    primitives::event_stream::EventReceiver,
+    types::{
+        error::RetrieveAndGenerateStreamResponseOutputError, GuadrailAction, KnowledgeBaseQuery,
+        KnowledgeBaseRetrievalConfiguration, KnowledgeBaseRetrieveAndGenerateConfiguration,
+        KnowledgeBaseVectorSearchConfiguration, RetrieveAndGenerateConfiguration,
+        RetrieveAndGenerateInput, RetrieveAndGenerateStreamResponseOutput,
+    },
 };
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{cmp::Ordering, fmt::Debug, future::Future};
+
+pub mod citation;
+pub mod data_sync;
+pub mod retriever;
+pub mod settings;
+pub mod sync_selection;
 
-use crate::env_keys::{CHAT_MODEL_ID, KNOWLEDGE_BASE_ID};
+use citation::{dedup_citations, insert_markers, presign_s3_links, Citation};
+use data_sync::SyncConflictPolicy;
+use settings::RetrievalSettings;
+
+use crate::{
+    config::Config,
+    env_keys::{CHAT_MODEL_ID, KNOWLEDGE_BASE_ID},
+    error::AnswerError,
+    service::s3_presigner::S3Presigner,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RetrievalResult {
     pub text: String,
-    pub reference_urls: Vec<String>,
+    /// Deduplicated, in the order they are first cited.
+    pub citations: Vec<Citation>,
+    pub session_id: String,
+}
+
+impl RetrievalResult {
+    /// Where each source is. Unlike the links, these do not expire.
+    pub fn reference_urls(&self) -> Vec<String> {
+        self.citations
+            .iter()
+            .map(|c| c.location.to_owned())
+            .collect()
+    }
+
+    /// The text with `[n]` after each part the `n`th citation supports.
+    pub fn text_with_markers(&self) -> String {
+        insert_markers(&self.text, &self.citations)
+    }
+}
+
+/// A source matching a query, without any generated answer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchMatch {
+    pub citation: Citation,
+    /// Relevance of the best matching chunk of the source, higher is better.
+    pub score: Option<f64>,
 }
 
+// pages to show for a search, before merging the chunks of the same page.
+const SEARCH_NUMBER_OF_RESULTS: i32 = 10;
+pub const MAX_SEARCH_MATCHES: usize = 5;
+
+// the errors of a session that is unknown, or expired.
+const RESOURCE_NOT_FOUND_ERROR_CODE: &str = "ResourceNotFoundException";
+const VALIDATION_ERROR_CODE: &str = "ValidationException";
+
 #[derive(Debug, Clone)]
 pub struct BedrockService {
     runtime_client: aws_sdk_bedrockagentruntime::Client,
     client: aws_sdk_bedrockagent::Client,
+    chat_model_id: Option<String>,
+    knowledge_base_id: Option<String>,
+    sync_conflict_policy: SyncConflictPolicy,
+    presigner: S3Presigner,
 }
 
 impl BedrockService {
     pub fn new(
         runtime_client: &aws_sdk_bedrockagentruntime::Client,
         client: &aws_sdk_bedrockagent::Client,
+        presigner: &S3Presigner,
+        config: &Config,
     ) -> Self {
         Self {
             runtime_client: runtime_client.to_owned(),
             client: client.to_owned(),
+            chat_model_id: config.chat_model_id.to_owned(),
+            knowledge_base_id: config.knowledge_base_id.to_owned(),
+            sync_conflict_policy: config.sync_conflict_policy,
+            presigner: presigner.to_owned(),
         }
     }
 
-    pub async fn start_data_sync(&self) -> Result<()> {
-        let knowledge_base_id = env::var(KNOWLEDGE_BASE_ID)?;
-        let datasource_stream = self
-            .client
-            .list_data_sources()
-            .knowledge_base_id(&knowledge_base_id)
-            .into_paginator()
-            .send();
-        let results = datasource_stream
-            .collect::<Vec<
-                Result<
-                    ListDataSourcesOutput,
-                    aws_sdk_bedrockagent::error::SdkError<ListDataSourcesError, _>,
-                >,
-            >>()
-            .await;
+    fn knowledge_base_id(&self) -> Result<&str> {
+        self.knowledge_base_id
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", KNOWLEDGE_BASE_ID))
+    }
 
-        let results: Result<Vec<ListDataSourcesOutput>, _> =
-            results.into_iter().map(|r| r).collect();
+    /// Pass the `session_id` of a previous retrieval to continue the same conversation.
+    /// If Bedrock rejects the session (ex: expired), a new session is started instead
+    /// and its id is returned in the result.
+    /// Fails with an [`AnswerError`] if nothing relevant is found or a guardrail intervenes.
+    pub async fn retrieve(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalResult> {
+        let (input, configuration) = self.build_request(input_query, settings)?;
+
+        let response = send_with_session_retry(session_id, |session_id| {
+            self.runtime_client
+                .retrieve_and_generate()
+                .input(input.clone())
+                .retrieve_and_generate_configuration(configuration.clone())
+                .set_session_id(session_id)
+                .send()
+        })
+        .await?;
 
-        let summaries: Vec<DataSourceSummary> = match results {
-            Ok(r) => r
-                .iter()
-                .flat_map(|r| r.data_source_summaries().to_vec())
-                .collect(),
+        if response.guardrail_action() == Some(&GuadrailAction::Intervened) {
+            bail!(AnswerError::GuardrailBlocked)
+        }
+
+        let text = response.output().map(|o| o.text()).unwrap_or_default();
+        let retrieved = response
+            .citations()
+            .iter()
+            .any(|c| !c.retrieved_references().is_empty());
+        let continued = session_id == Some(response.session_id());
+        if is_no_relevant_content(text, retrieved, continued) {
+            bail!(AnswerError::NoRelevantContent)
+        }
+
+        let citations = response
+            .citations()
+            .iter()
+            .flat_map(|c| {
+                Citation::from_part(c.generated_response_part(), c.retrieved_references())
+            })
+            .collect();
+        let mut citations = dedup_citations(citations);
+        presign_s3_links(&mut citations, &self.presigner).await;
+
+        Ok(RetrievalResult {
+            text: text.to_owned(),
+            citations,
+            session_id: response.session_id().to_owned(),
+        })
+    }
+
+    /// The sources that best match the query, best first, with the `Retrieve` API.
+    /// Nothing is generated, so this is cheaper and faster than [`BedrockService::retrieve`].
+    /// Chunks of the same source are merged, and at most [`MAX_SEARCH_MATCHES`] are returned.
+    /// Only the search type of the `settings` is used, as the number of results is fixed.
+    /// Fails with [`AnswerError::NoRelevantContent`] if nothing matches.
+    pub async fn search(
+        &self,
+        query: &str,
+        settings: &RetrievalSettings,
+    ) -> Result<Vec<SearchMatch>> {
+        let knowledge_base_id = self.knowledge_base_id()?;
+
+        let vector_search_configuration = KnowledgeBaseVectorSearchConfiguration::builder()
+            .number_of_results(SEARCH_NUMBER_OF_RESULTS)
+            .set_override_search_type(settings.search_type.map(|t| t.sdk_type()))
+            .build();
+        let configuration = KnowledgeBaseRetrievalConfiguration::builder()
+            .vector_search_configuration(vector_search_configuration)
+            .build();
+
+        let response = self
+            .runtime_client
+            .retrieve()
+            .knowledge_base_id(knowledge_base_id)
+            .retrieval_query(KnowledgeBaseQuery::builder().text(query).build()?)
+            .retrieval_configuration(configuration)
+            .send()
+            .await;
+        let response = match response {
+            Ok(r) => r,
             Err(error) => {
-                println!("Error getting datasource summary: {}, ", error);
+                println!("error retrieving: {}", error);
                 bail!(error)
             }
         };
-        let datasource_ids: Vec<String> = summaries
-            .iter()
-            .map(|s| s.data_source_id().to_owned())
-            .collect();
 
-        println!("syncing data soucres: {:?}", datasource_ids);
+        if response.guardrail_action() == Some(&GuadrailAction::Intervened) {
+            bail!(AnswerError::GuardrailBlocked)
+        }
 
-        for id in datasource_ids {
-            let result = self
-                .client
-                .start_ingestion_job()
-                .knowledge_base_id(&knowledge_base_id)
-                .data_source_id(id)
-                .send()
-                .await;
-            if let Err(error) = result {
-                println!("Error starting data sync: {}", error)
+        let mut matches: Vec<SearchMatch> = vec![];
+        for result in response.retrieval_results() {
+            let Some(citation) = Citation::from_retrieval_result(result) else {
+                continue;
+            };
+            match matches
+                .iter_mut()
+                .find(|m| m.citation.location == citation.location)
+            {
+                Some(existing) => {
+                    if result.score() > existing.score {
+                        existing.score = result.score();
+                        existing.citation.snippet =
+                            citation.snippet.or(existing.citation.snippet.take());
+                    }
+                }
+                None => matches.push(SearchMatch {
+                    citation,
+                    score: result.score(),
+                }),
             }
         }
+        if matches.is_empty() {
+            bail!(AnswerError::NoRelevantContent)
+        }
+
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        matches.truncate(MAX_SEARCH_MATCHES);
+
+        for search_match in matches.iter_mut() {
+            presign_s3_links(
+                std::slice::from_mut(&mut search_match.citation),
+                &self.presigner,
+            )
+            .await;
+        }
 
-        Ok(())
+        Ok(matches)
     }
 
-    pub async fn retrieve(&self, input_query: &str) -> Result<RetrievalResult> {
-        let model_arn = env::var(CHAT_MODEL_ID)?;
-        let knowledge_base_id = env::var(KNOWLEDGE_BASE_ID)?;
+    /// Same as [`BedrockService::retrieve`], but the generated text is received chunk by chunk.
+    pub async fn retrieve_stream(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalStream> {
+        let (input, configuration) = self.build_request(input_query, settings)?;
+
+        let response = send_with_session_retry(session_id, |session_id| {
+            self.runtime_client
+                .retrieve_and_generate_stream()
+                .input(input.clone())
+                .retrieve_and_generate_configuration(configuration.clone())
+                .set_session_id(session_id)
+                .send()
+        })
+        .await?;
+
+        Ok(RetrievalStream {
+            continued: session_id == Some(response.session_id()),
+            session_id: response.session_id().to_owned(),
+            stream: response.stream,
+            text: String::new(),
+            citations: vec![],
+            retrieved: false,
+            presigner: self.presigner.to_owned(),
+            guardrail_intervened: false,
+        })
+    }
+
+    fn build_request(
+        &self,
+        input_query: &str,
+        settings: &RetrievalSettings,
+    ) -> Result<(RetrieveAndGenerateInput, RetrieveAndGenerateConfiguration)> {
+        let model_arn = self
+            .chat_model_id
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", CHAT_MODEL_ID))?;
+        let knowledge_base_id = self.knowledge_base_id()?;
 
         let input = RetrieveAndGenerateInput::builder()
             .text(input_query)
@@ -99,6 +283,8 @@ impl BedrockService {
         let knowbase_configuration = KnowledgeBaseRetrieveAndGenerateConfiguration::builder()
             .knowledge_base_id(knowledge_base_id)
             .model_arn(model_arn)
+            .set_retrieval_configuration(settings.retrieval_configuration())
+            .set_generation_configuration(settings.generation_configuration())
             .build()?;
 
         let configuration = RetrieveAndGenerateConfiguration::builder()
@@ -106,57 +292,172 @@ impl BedrockService {
             .r#type(aws_sdk_bedrockagentruntime::types::RetrieveAndGenerateType::KnowledgeBase)
             .build()?;
 
-        let response = self
-            .runtime_client
-            .retrieve_and_generate()
-            .input(input)
-            .retrieve_and_generate_configuration(configuration)
-            .send()
-            .await;
+        Ok((input, configuration))
+    }
+}
 
-        let response = match response {
-            Ok(r) => r,
-            Err(error) => {
-                println!("error getting response: {}", error);
-                bail!(error)
+/// Generated text of a `retrieve_and_generate_stream` call.
+/// Citations are collected while reading and only available once the stream finishes.
+#[derive(Debug)]
+pub struct RetrievalStream {
+    stream: EventReceiver<
+        RetrieveAndGenerateStreamResponseOutput,
+        RetrieveAndGenerateStreamResponseOutputError,
+    >,
+    session_id: String,
+    continued: bool,
+    text: String,
+    citations: Vec<Citation>,
+    retrieved: bool,
+    guardrail_intervened: bool,
+    presigner: S3Presigner,
+}
+
+impl RetrievalStream {
+    /// Next chunk of the generated text, or `None` when the stream finishes.
+    pub async fn next_chunk(&mut self) -> Result<Option<String>> {
+        loop {
+            let event = match self.stream.recv().await {
+                Ok(Some(e)) => e,
+                Ok(None) => return Ok(None),
+                Err(error) => {
+                    println!("error receiving stream event: {}", error);
+                    bail!(error)
+                }
+            };
+
+            match event {
+                RetrieveAndGenerateStreamResponseOutput::Output(output) => {
+                    self.text.push_str(output.text());
+                    return Ok(Some(output.text().to_owned()));
+                }
+                RetrieveAndGenerateStreamResponseOutput::Citation(citation) => {
+                    let references = citation.retrieved_references();
+                    self.retrieved |= !references.is_empty();
+                    self.citations.extend(Citation::from_part(
+                        citation.generated_response_part(),
+                        references,
+                    ));
+                }
+                RetrieveAndGenerateStreamResponseOutput::Guardrail(guardrail)
+                    if guardrail.action() == Some(&GuadrailAction::Intervened) =>
+                {
+                    self.guardrail_intervened = true;
+                }
+                _ => {}
             }
-        };
+        }
+    }
 
-        let Some(output) = response.output() else {
-            bail!("Fail to generate an output for the input.")
-        };
+    /// The text generated so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
 
-        let citations = response.citations();
+    /// Fails like [`BedrockService::retrieve`] once the stream finishes,
+    /// so that the streamed text is replaced by the error reply.
+    pub async fn into_result(self) -> Result<RetrievalResult> {
+        if self.guardrail_intervened {
+            bail!(AnswerError::GuardrailBlocked)
+        }
+        if is_no_relevant_content(&self.text, self.retrieved, self.continued) {
+            bail!(AnswerError::NoRelevantContent)
+        }
 
-        let reference_urls: Vec<String> = citations
-            .iter()
-            .flat_map(|c| c.retrieved_references().to_owned())
-            .filter(|r| {
-                r.location().is_some()
-                    && r.location.clone().unwrap().r#type()
-                        == &RetrievalResultLocationType::Confluence
-                    && r.location().unwrap().confluence_location().is_some()
-                    && r.location()
-                        .unwrap()
-                        .confluence_location()
-                        .unwrap()
-                        .url()
-                        .is_some()
-            })
-            .map(|r| {
-                r.location()
-                    .unwrap()
-                    .confluence_location()
-                    .unwrap()
-                    .url()
-                    .unwrap()
-                    .to_owned()
-            })
-            .collect();
+        let mut citations = dedup_citations(self.citations);
+        presign_s3_links(&mut citations, &self.presigner).await;
 
         Ok(RetrievalResult {
-            text: output.text().to_owned(),
-            reference_urls,
+            citations,
+            text: self.text,
+            session_id: self.session_id,
         })
     }
 }
+
+// when the knowledge base returns nothing, the model still generates a text apologizing for it,
+// without any retrieved reference.
+// a follow-up in a conversation can be answered from the previous turns alone, though.
+fn is_no_relevant_content(text: &str, retrieved: bool, continued: bool) -> bool {
+    text.trim().is_empty() || (!retrieved && !continued)
+}
+
+// Sends the request in the session, or in a new one if Bedrock rejects the session (ex: expired).
+async fn send_with_session_retry<T, E, R, F, Fut>(session_id: Option<&str>, send: F) -> Result<T>
+where
+    F: Fn(Option<String>) -> Fut,
+    Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: Debug + Send + Sync + 'static,
+{
+    let error = match send(session_id.map(|s| s.to_owned())).await {
+        Ok(response) => return Ok(response),
+        Err(error) if session_id.is_some_and(|id| is_invalid_session_error(&error, id)) => {
+            println!(
+                "session {:?} rejected, starting a new one: {}",
+                session_id, error
+            );
+            match send(None).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            }
+        }
+        Err(error) => error,
+    };
+    println!("error getting response: {}", error);
+    bail!(error)
+}
+
+// An unknown session is reported as not found, an expired one as a validation error naming it,
+// ex: `Session with Id <session_id> is not valid.`
+// Other validation errors, ex: a too long question, would fail the same without the session.
+fn is_invalid_session_error<E: ProvideErrorMetadata, R>(
+    error: &SdkError<E, R>,
+    session_id: &str,
+) -> bool {
+    let Some(e) = error.as_service_error() else {
+        return false;
+    };
+    match e.code() {
+        Some(RESOURCE_NOT_FOUND_ERROR_CODE) => true,
+        Some(VALIDATION_ERROR_CODE) => e.message().is_some_and(|m| m.contains(session_id)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_text_is_no_relevant_content() {
+        for (retrieved, continued) in [(true, true), (true, false), (false, true), (false, false)] {
+            assert!(is_no_relevant_content("", retrieved, continued));
+            assert!(is_no_relevant_content(" \n", retrieved, continued));
+        }
+    }
+
+    #[test]
+    fn answer_with_retrieved_references_is_kept() {
+        assert!(!is_no_relevant_content("Run the deploy job.", true, false));
+        assert!(!is_no_relevant_content("Run the deploy job.", true, true));
+    }
+
+    #[test]
+    fn new_conversation_without_retrieved_references_is_no_relevant_content() {
+        assert!(is_no_relevant_content(
+            "Sorry, I am unable to assist you with this request.",
+            false,
+            false
+        ));
+    }
+
+    #[test]
+    fn follow_up_answered_from_the_session_is_kept() {
+        assert!(!is_no_relevant_content(
+            "As I said above, run the deploy job.",
+            false,
+            true
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    settings::RetrievalSettings, BedrockService, RetrievalResult, RetrievalStream, SearchMatch,
+};
+use crate::error::AnswerError;
+
+/// Answers questions from the knowledge base.
+#[async_trait]
+pub trait KnowledgeRetriever: Debug + Send + Sync {
+    /// Pass the `session_id` of a previous retrieval to continue the same conversation.
+    async fn retrieve(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalResult>;
+
+    /// Same as [`KnowledgeRetriever::retrieve`], but the generated text is received chunk by chunk.
+    async fn retrieve_stream(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<Box<dyn AnswerStream>>;
+
+    /// The sources that best match the query, best first, without generating an answer.
+    async fn search(&self, query: &str, settings: &RetrievalSettings) -> Result<Vec<SearchMatch>>;
+}
+
+/// Generated text received chunk by chunk.
+#[async_trait]
+pub trait AnswerStream: Send {
+    /// Next chunk of the generated text, or `None` when the stream finishes.
+    async fn next_chunk(&mut self) -> Result<Option<String>>;
+
+    /// The text generated so far.
+    fn text(&self) -> &str;
+
+    async fn into_result(self: Box<Self>) -> Result<RetrievalResult>;
+}
+
+#[async_trait]
+impl KnowledgeRetriever for BedrockService {
+    async fn retrieve(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalResult> {
+        BedrockService::retrieve(self, input_query, session_id, settings).await
+    }
+
+    async fn retrieve_stream(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<Box<dyn AnswerStream>> {
+        let stream =
+            BedrockService::retrieve_stream(self, input_query, session_id, settings).await?;
+        Ok(Box::new(stream))
+    }
+
+    async fn search(&self, query: &str, settings: &RetrievalSettings) -> Result<Vec<SearchMatch>> {
+        BedrockService::search(self, query, settings).await
+    }
+}
+
+#[async_trait]
+impl AnswerStream for RetrievalStream {
+    async fn next_chunk(&mut self) -> Result<Option<String>> {
+        RetrievalStream::next_chunk(self).await
+    }
+
+    fn text(&self) -> &str {
+        RetrievalStream::text(self)
+    }
+
+    async fn into_result(self: Box<Self>) -> Result<RetrievalResult> {
+        RetrievalStream::into_result(*self).await
+    }
+}
+
+/// A question asked to an [`InMemoryKnowledgeRetriever`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievalRequest {
+    pub input_query: String,
+    pub session_id: Option<String>,
+    pub settings: RetrievalSettings,
+}
+
+/// Answers with the results pushed beforehand, in order, for tests and local runs.
+/// Once they run out, fails with [`AnswerError::NoRelevantContent`].
+/// Searches are answered the same way, from their own results.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryKnowledgeRetriever {
+    results: Arc<Mutex<VecDeque<RetrievalResult>>>,
+    search_results: Arc<Mutex<VecDeque<Vec<SearchMatch>>>>,
+    requests: Arc<Mutex<Vec<RetrievalRequest>>>,
+}
+
+impl InMemoryKnowledgeRetriever {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_result(&self, result: RetrievalResult) {
+        if let Ok(mut results) = self.results.lock() {
+            results.push_back(result);
+        }
+    }
+
+    pub fn push_search_result(&self, matches: Vec<SearchMatch>) {
+        if let Ok(mut results) = self.search_results.lock() {
+            results.push_back(matches);
+        }
+    }
+
+    /// The questions asked so far, searches included, oldest first.
+    pub fn requests(&self) -> Vec<RetrievalRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+
+    fn next_result(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalResult> {
+        self.record_request(input_query, session_id, settings)?;
+
+        let result = self
+            .results
+            .lock()
+            .map_err(|_| anyhow!("retriever lock poisoned."))?
+            .pop_front();
+        match result {
+            Some(result) => Ok(result),
+            None => bail!(AnswerError::NoRelevantContent),
+        }
+    }
+
+    fn next_search_result(
+        &self,
+        query: &str,
+        settings: &RetrievalSettings,
+    ) -> Result<Vec<SearchMatch>> {
+        self.record_request(query, None, settings)?;
+
+        let matches = self
+            .search_results
+            .lock()
+            .map_err(|_| anyhow!("retriever lock poisoned."))?
+            .pop_front();
+        match matches {
+            Some(matches) if !matches.is_empty() => Ok(matches),
+            _ => bail!(AnswerError::NoRelevantContent),
+        }
+    }
+
+    fn record_request(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<()> {
+        self.requests
+            .lock()
+            .map_err(|_| anyhow!("retriever lock poisoned."))?
+            .push(RetrievalRequest {
+                input_query: input_query.to_owned(),
+                session_id: session_id.map(|s| s.to_owned()),
+                settings: settings.to_owned(),
+            });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl KnowledgeRetriever for InMemoryKnowledgeRetriever {
+    async fn retrieve(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<RetrievalResult> {
+        self.next_result(input_query, session_id, settings)
+    }
+
+    async fn retrieve_stream(
+        &self,
+        input_query: &str,
+        session_id: Option<&str>,
+        settings: &RetrievalSettings,
+    ) -> Result<Box<dyn AnswerStream>> {
+        let result = self.next_result(input_query, session_id, settings)?;
+        Ok(Box::new(InMemoryAnswerStream::new(result)))
+    }
+
+    async fn search(&self, query: &str, settings: &RetrievalSettings) -> Result<Vec<SearchMatch>> {
+        self.next_search_result(query, settings)
+    }
+}
+
+/// Streams the text of a result word by word.
+#[derive(Debug, Clone)]
+pub struct InMemoryAnswerStream {
+    chunks: VecDeque<String>,
+    text: String,
+    result: RetrievalResult,
+}
+
+impl InMemoryAnswerStream {
+    pub fn new(result: RetrievalResult) -> Self {
+        Self {
+            chunks: result
+                .text
+                .split_inclusive(' ')
+                .map(|chunk| chunk.to_owned())
+                .collect(),
+            text: String::new(),
+            result,
+        }
+    }
+}
+
+#[async_trait]
+impl AnswerStream for InMemoryAnswerStream {
+    async fn next_chunk(&mut self) -> Result<Option<String>> {
+        let chunk = self.chunks.pop_front();
+        if let Some(chunk) = &chunk {
+            self.text.push_str(chunk);
+        }
+        Ok(chunk)
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    async fn into_result(self: Box<Self>) -> Result<RetrievalResult> {
+        Ok(self.result)
+    }
+}
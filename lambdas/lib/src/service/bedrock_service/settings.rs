@@ -0,0 +1,145 @@
+use anyhow::{bail, Result};
+use aws_sdk_bedrockagentruntime::types::{
+    GenerationConfiguration, InferenceConfig, KnowledgeBaseRetrievalConfiguration,
+    KnowledgeBaseVectorSearchConfiguration, TextInferenceConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, ops::RangeInclusive};
+
+// https://docs.aws.amazon.com/bedrock/latest/APIReference/API_agent-runtime_KnowledgeBaseVectorSearchConfiguration.html
+pub const NUMBER_OF_RESULTS_RANGE: RangeInclusive<i32> = 1..=100;
+// temperature and top p.
+pub const PROBABILITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+/// How the chunks are searched for in the vector store.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SearchType {
+    /// Semantic and text search combined, only for some vector stores, ex: OpenSearch Serverless.
+    Hybrid,
+    Semantic,
+}
+
+impl SearchType {
+    /// `HYBRID` or `SEMANTIC`, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "HYBRID" => Some(SearchType::Hybrid),
+            "SEMANTIC" => Some(SearchType::Semantic),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn sdk_type(&self) -> aws_sdk_bedrockagentruntime::types::SearchType {
+        match self {
+            SearchType::Hybrid => aws_sdk_bedrockagentruntime::types::SearchType::Hybrid,
+            SearchType::Semantic => aws_sdk_bedrockagentruntime::types::SearchType::Semantic,
+        }
+    }
+}
+
+/// How many chunks are retrieved and how the answer is generated from them.
+/// Unset values are left to Bedrock, ex: 5 results and the model's own inference parameters.
+///
+/// Set globally in the [`crate::config::Config`], and per channel as JSON, ex:
+/// `{"number_of_results": 10, "search_type": "HYBRID", "temperature": 0.2}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetrievalSettings {
+    pub number_of_results: Option<i32>,
+    pub search_type: Option<SearchType>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub top_p: Option<f32>,
+}
+
+impl RetrievalSettings {
+    /// These settings, with the values set in `overrides` taking precedence.
+    pub fn merge(&self, overrides: &RetrievalSettings) -> Self {
+        Self {
+            number_of_results: overrides.number_of_results.or(self.number_of_results),
+            search_type: overrides.search_type.or(self.search_type),
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            top_p: overrides.top_p.or(self.top_p),
+        }
+    }
+
+    /// Fail on the values Bedrock would reject.
+    pub fn validate(&self) -> Result<()> {
+        check_range(
+            "number_of_results",
+            self.number_of_results,
+            NUMBER_OF_RESULTS_RANGE,
+        )?;
+        check_range("temperature", self.temperature, PROBABILITY_RANGE)?;
+        check_range("top_p", self.top_p, PROBABILITY_RANGE)?;
+        check_positive("max_tokens", self.max_tokens)?;
+        Ok(())
+    }
+
+    /// `None` if nothing is set, to keep Bedrock's defaults.
+    pub(crate) fn retrieval_configuration(&self) -> Option<KnowledgeBaseRetrievalConfiguration> {
+        if self.number_of_results.is_none() && self.search_type.is_none() {
+            return None;
+        }
+
+        let vector_search_configuration = KnowledgeBaseVectorSearchConfiguration::builder()
+            .set_number_of_results(self.number_of_results)
+            .set_override_search_type(self.search_type.map(|t| t.sdk_type()))
+            .build();
+        Some(
+            KnowledgeBaseRetrievalConfiguration::builder()
+                .vector_search_configuration(vector_search_configuration)
+                .build(),
+        )
+    }
+
+    /// `None` if nothing is set, to keep the model's defaults.
+    pub(crate) fn generation_configuration(&self) -> Option<GenerationConfiguration> {
+        if self.temperature.is_none() && self.max_tokens.is_none() && self.top_p.is_none() {
+            return None;
+        }
+
+        let text_inference_config = TextInferenceConfig::builder()
+            .set_temperature(self.temperature)
+            .set_max_tokens(self.max_tokens)
+            .set_top_p(self.top_p)
+            .build();
+        Some(
+            GenerationConfiguration::builder()
+                .inference_config(
+                    InferenceConfig::builder()
+                        .text_inference_config(text_inference_config)
+                        .build(),
+                )
+                .build(),
+        )
+    }
+}
+
+/// Fail if `value` is set and out of `range`, `name` being what it was given as.
+pub fn check_range<T: PartialOrd + Display>(
+    name: &str,
+    value: Option<T>,
+    range: RangeInclusive<T>,
+) -> Result<()> {
+    match value {
+        Some(value) if !range.contains(&value) => bail!(
+            "{} should be between {} and {}, got `{}`.",
+            name,
+            range.start(),
+            range.end(),
+            value
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Fail if `value` is set and not positive, `name` being what it was given as.
+pub fn check_positive(name: &str, value: Option<i32>) -> Result<()> {
+    match value {
+        Some(value) if value < 1 => bail!("{} should be positive, got `{}`.", name, value),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// the frequency of the data sources not listed in the frequencies.
+pub const DEFAULT_FREQUENCY_KEY: &str = "*";
+
+/// How often a data source is synced by the scheduled syncs. All of them run at 00:00 UTC, but `hourly`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncFrequency {
+    Hourly,
+    Daily,
+    /// Monday to Friday, as the sync always was.
+    #[default]
+    Weekdays,
+    /// On Mondays.
+    Weekly,
+}
+
+impl SyncFrequency {
+    /// Whether a sync scheduled at `at` is one of this frequency.
+    pub fn is_due(&self, at: DateTime<Utc>) -> bool {
+        let midnight = at.hour() == 0;
+        match self {
+            SyncFrequency::Hourly => true,
+            SyncFrequency::Daily => midnight,
+            SyncFrequency::Weekdays => {
+                midnight && !matches!(at.weekday(), Weekday::Sat | Weekday::Sun)
+            }
+            SyncFrequency::Weekly => midnight && at.weekday() == Weekday::Mon,
+        }
+    }
+}
+
+/// The data sources to sync, as configured or given to the sync state machine,
+/// ex: `{"include": ["eng-*"], "exclude": ["eng-archive"]}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SyncFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl SyncFilter {
+    /// Nothing set, every data source.
+    pub fn is_empty(&self) -> bool {
+        self.data_sources.is_empty() && self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// Which data sources a sync is for, see [`SyncFilter`] for the fields they share.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncSelection {
+    /// IDs or names, synced whatever the rules below say.
+    pub data_sources: Vec<String>,
+    /// Patterns of IDs or names, ex: `eng-*`, matched in any case with `*` and `?`.
+    /// Every data source is included if empty.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// For a scheduled sync, only the data sources whose frequency is due at that time are synced.
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// By data source ID or name, [`DEFAULT_FREQUENCY_KEY`] for the others.
+    pub frequencies: HashMap<String, SyncFrequency>,
+}
+
+impl SyncSelection {
+    /// Pick the data sources to sync out of all of them, `id_and_name` telling what each one is.
+    /// Fails if any of the `data_sources` is not among them.
+    pub fn select<T>(
+        &self,
+        all: Vec<T>,
+        id_and_name: impl Fn(&T) -> (&str, &str),
+    ) -> Result<Vec<T>> {
+        if self.data_sources.is_empty() {
+            return Ok(all
+                .into_iter()
+                .filter(|data_source| {
+                    let (id, name) = id_and_name(data_source);
+                    self.is_selected(id, name)
+                })
+                .collect());
+        }
+
+        let is_given = |data_source: &T, given: &str| {
+            let (id, name) = id_and_name(data_source);
+            id == given || name.eq_ignore_ascii_case(given)
+        };
+        let unknown: Vec<&str> = self
+            .data_sources
+            .iter()
+            .filter(|given| !all.iter().any(|data_source| is_given(data_source, given)))
+            .map(|given| given.as_str())
+            .collect();
+        if !unknown.is_empty() {
+            let names: Vec<&str> = all.iter().map(|d| id_and_name(d).1).collect();
+            bail!(
+                "no data source {} in the knowledge base, the data sources are: {}.",
+                unknown.join(", "),
+                names.join(", ")
+            );
+        }
+
+        Ok(all
+            .into_iter()
+            .filter(|data_source| self.data_sources.iter().any(|g| is_given(data_source, g)))
+            .collect())
+    }
+
+    /// Of the data source with `id` and `name`, by ID first.
+    pub fn frequency(&self, id: &str, name: &str) -> SyncFrequency {
+        self.frequencies
+            .get(id)
+            .or_else(|| {
+                self.frequencies
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, frequency)| frequency)
+            })
+            .or_else(|| self.frequencies.get(DEFAULT_FREQUENCY_KEY))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn is_selected(&self, id: &str, name: &str) -> bool {
+        let matches =
+            |pattern: &String| matches_pattern(pattern, id) || matches_pattern(pattern, name);
+        let included = self.include.is_empty() || self.include.iter().any(matches);
+        let excluded = self.exclude.iter().any(matches);
+        let due = self
+            .scheduled_at
+            .is_none_or(|at| self.frequency(id, name).is_due(at));
+        included && !excluded && due
+    }
+}
+
+/// Whether `text` matches `pattern` in any case, `*` matching any run of characters and `?` any one.
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // the last `*` seen and where in the text it was tried up to, to backtrack to.
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const DATA_SOURCES: [(&str, &str); 4] = [
+        ("DS1", "engineering"),
+        ("DS2", "eng-archive"),
+        ("DS3", "Support"),
+        ("DS4", "marketing"),
+    ];
+
+    fn select(selection: &SyncSelection) -> Result<Vec<&'static str>> {
+        selection
+            .select(DATA_SOURCES.to_vec(), |(id, name)| (id, name))
+            .map(|selected| selected.into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn frequencies(frequencies: &[(&str, SyncFrequency)]) -> HashMap<String, SyncFrequency> {
+        frequencies
+            .iter()
+            .map(|(key, frequency)| (key.to_string(), *frequency))
+            .collect()
+    }
+
+    // 2026-10-19 is a Monday.
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn patterns_match_in_any_case() {
+        assert!(matches_pattern("eng-*", "eng-archive"));
+        assert!(matches_pattern("ENG*", "engineering"));
+        assert!(matches_pattern("*ing", "marketing"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("DS?", "ds1"));
+        assert!(matches_pattern("e*g*g", "engineering"));
+        assert!(matches_pattern("support", "Support"));
+
+        assert!(!matches_pattern("eng-*", "engineering"));
+        assert!(!matches_pattern("DS?", "DS10"));
+        assert!(!matches_pattern("support", "support-team"));
+        assert!(!matches_pattern("", "a"));
+    }
+
+    #[test]
+    fn nothing_set_selects_every_data_source() {
+        let selection = SyncSelection::default();
+        assert_eq!(
+            select(&selection).unwrap(),
+            vec!["DS1", "DS2", "DS3", "DS4"]
+        );
+        assert!(SyncFilter::default().is_empty());
+    }
+
+    #[test]
+    fn include_and_exclude_match_ids_or_names() {
+        let selection = SyncSelection {
+            include: vec!["eng*".to_owned(), "DS3".to_owned()],
+            exclude: vec!["*archive".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(select(&selection).unwrap(), vec!["DS1", "DS3"]);
+    }
+
+    #[test]
+    fn given_data_sources_are_synced_whatever_the_patterns() {
+        let selection = SyncSelection {
+            data_sources: vec!["support".to_owned(), "DS2".to_owned()],
+            exclude: vec!["*".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(select(&selection).unwrap(), vec!["DS2", "DS3"]);
+    }
+
+    #[test]
+    fn unknown_data_sources_fail() {
+        let selection = SyncSelection {
+            data_sources: vec!["DS1".to_owned(), "sales".to_owned(), "DS9".to_owned()],
+            ..Default::default()
+        };
+        let error = select(&selection).unwrap_err().to_string();
+        assert!(error.contains("no data source sales, DS9"), "{}", error);
+        assert!(error.contains("engineering, eng-archive, Support, marketing"));
+    }
+
+    #[test]
+    fn bad_frequencies_fail_to_parse() {
+        assert_eq!(
+            serde_json::from_str::<SyncFrequency>(r#""hourly""#).unwrap(),
+            SyncFrequency::Hourly
+        );
+        assert!(serde_json::from_str::<SyncFrequency>(r#""monthly""#).is_err());
+        assert!(serde_json::from_str::<SyncFrequency>(r#""Daily""#).is_err());
+        assert!(serde_json::from_str::<HashMap<String, SyncFrequency>>(r#"{"DS1": 1}"#).is_err());
+    }
+
+    #[test]
+    fn frequency_is_by_id_then_name_then_default() {
+        let selection = SyncSelection {
+            frequencies: frequencies(&[
+                ("DS1", SyncFrequency::Hourly),
+                ("ENGINEERING", SyncFrequency::Weekly),
+                ("support", SyncFrequency::Daily),
+                (DEFAULT_FREQUENCY_KEY, SyncFrequency::Weekly),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.frequency("DS1", "engineering"),
+            SyncFrequency::Hourly
+        );
+        assert_eq!(selection.frequency("DS3", "Support"), SyncFrequency::Daily);
+        assert_eq!(
+            selection.frequency("DS4", "marketing"),
+            SyncFrequency::Weekly
+        );
+
+        let selection = SyncSelection::default();
+        assert_eq!(
+            selection.frequency("DS4", "marketing"),
+            SyncFrequency::Weekdays
+        );
+    }
+
+    #[test]
+    fn frequencies_are_due() {
+        let monday = at(19, 0);
+        let saturday = at(17, 0);
+        let monday_noon = at(19, 12);
+
+        assert!(SyncFrequency::Hourly.is_due(monday_noon));
+        assert!(SyncFrequency::Daily.is_due(saturday));
+        assert!(!SyncFrequency::Daily.is_due(monday_noon));
+        assert!(SyncFrequency::Weekdays.is_due(monday));
+        assert!(!SyncFrequency::Weekdays.is_due(saturday));
+        assert!(SyncFrequency::Weekly.is_due(monday));
+        assert!(!SyncFrequency::Weekly.is_due(at(20, 0)));
+    }
+
+    #[test]
+    fn scheduled_sync_selects_the_data_sources_due() {
+        let mut selection = SyncSelection {
+            frequencies: frequencies(&[
+                ("engineering", SyncFrequency::Hourly),
+                ("DS2", SyncFrequency::Weekly),
+                (DEFAULT_FREQUENCY_KEY, SyncFrequency::Daily),
+            ]),
+            ..Default::default()
+        };
+
+        // as any data source is hourly, the schedule runs every hour.
+        selection.scheduled_at = Some(at(20, 12));
+        assert_eq!(select(&selection).unwrap(), vec!["DS1"]);
+        selection.scheduled_at = Some(at(20, 0));
+        assert_eq!(select(&selection).unwrap(), vec!["DS1", "DS3", "DS4"]);
+        selection.scheduled_at = Some(at(19, 0));
+        assert_eq!(
+            select(&selection).unwrap(),
+            vec!["DS1", "DS2", "DS3", "DS4"]
+        );
+
+        // a sync not scheduled is of every data source, whatever its frequency.
+        selection.scheduled_at = None;
+        assert_eq!(
+            select(&selection).unwrap(),
+            vec!["DS1", "DS2", "DS3", "DS4"]
+        );
+    }
+
+    #[test]
+    fn scheduled_sync_without_frequencies_is_on_weekdays() {
+        let mut selection = SyncSelection {
+            scheduled_at: Some(at(17, 0)),
+            ..Default::default()
+        };
+        assert!(select(&selection).unwrap().is_empty());
+        selection.scheduled_at = Some(at(19, 0));
+        assert_eq!(select(&selection).unwrap().len(), DATA_SOURCES.len());
+    }
+
+    #[test]
+    fn given_data_sources_ignore_the_schedule() {
+        let selection = SyncSelection {
+            data_sources: vec!["DS4".to_owned()],
+            scheduled_at: Some(at(17, 0)),
+            ..Default::default()
+        };
+        assert_eq!(select(&selection).unwrap(), vec!["DS4"]);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use pages::{ConfluenceApiClient, ConfluencePage, ConfluencePages};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    config::Config,
+    env_keys::{CONFLUENCE_BASE_URL, CONFLUENCE_WEBHOOK_SECRET},
+    secrets::Secret,
+};
+
+pub mod pages;
+
+// https://developer.atlassian.com/cloud/confluence/modules/webhook/
+pub const PAGE_CREATED_EVENT: &str = "page_created";
+pub const PAGE_UPDATED_EVENT: &str = "page_updated";
+pub const PAGE_REMOVED_EVENT: &str = "page_removed";
+
+// `X-Hub-Signature: sha256=<hex>`, the HMAC of the body with the secret of the webhook.
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+// the data source of the spaces not listed.
+pub const DEFAULT_SPACE_KEY: &str = "*";
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(60 * 5);
+
+/// A page event sent by a Confluence Cloud webhook,
+/// ex: `{"webhookEvent": "page_updated", "page": {"id": 123, "spaceKey": "ENG", "title": "Runbook"}}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConfluenceWebhookEvent {
+    /// Left out by some webhooks, whose URL gives it instead.
+    #[serde(default, rename = "webhookEvent", alias = "event", alias = "eventType")]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub page: Option<WebhookPage>,
+}
+
+impl ConfluenceWebhookEvent {
+    /// A page was created, updated or removed, the other events being of no use to the sync.
+    pub fn is_page_event(event: &str) -> bool {
+        [PAGE_CREATED_EVENT, PAGE_UPDATED_EVENT, PAGE_REMOVED_EVENT].contains(&event)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct WebhookPage {
+    /// A number in the payload.
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(default, rename = "spaceKey")]
+    pub space_key: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(id) => Ok(id),
+        Value::Number(id) => Ok(id.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a page id, got {}",
+            other
+        ))),
+    }
+}
+
+/// The debounce window `at` falls in, and when it ends.
+/// The events of a window are synced together once it ends.
+pub fn debounce_window(at: DateTime<Utc>, debounce: Duration) -> (i64, DateTime<Utc>) {
+    let seconds = debounce.as_secs().max(1) as i64;
+    let window = at.timestamp().div_euclid(seconds);
+    let end = DateTime::from_timestamp((window + 1) * seconds, 0).unwrap_or(at);
+    (window, end)
+}
+
+/// Receives the Confluence webhooks and fetches the pages they are about.
+#[derive(Debug, Clone)]
+pub struct ConfluenceService {
+    pages: Arc<dyn ConfluencePages>,
+    webhook_secret: Secret,
+    base_url: Option<String>,
+}
+
+impl ConfluenceService {
+    pub fn new(config: &Config) -> Self {
+        Self::with_pages(config, Arc::new(ConfluenceApiClient::new(config)))
+    }
+
+    /// Fetch the pages through `pages`, ex: an [`pages::InMemoryConfluencePages`] in tests.
+    pub fn with_pages(config: &Config, pages: Arc<dyn ConfluencePages>) -> Self {
+        Self {
+            pages,
+            webhook_secret: Secret::new(
+                CONFLUENCE_WEBHOOK_SECRET,
+                config.confluence_webhook_secret_source(),
+                config.secrets_cache_ttl(),
+            ),
+            base_url: config.confluence_base_url.to_owned(),
+        }
+    }
+
+    /// Fetch the configured secrets, so that an unreachable or missing secret fails at cold start.
+    pub async fn load_secrets(&self) -> Result<()> {
+        if self.webhook_secret.is_configured() {
+            self.webhook_secret.get().await?;
+        }
+        self.pages.load_secrets().await
+    }
+
+    /// Whether the `X-Hub-Signature` of a webhook matches its body.
+    pub async fn verify_signature(&self, body: &[u8], received_signature: &str) -> Result<bool> {
+        let webhook_secret = self.webhook_secret.get().await?;
+
+        let Some(received) = received_signature
+            .strip_prefix(SIGNATURE_PREFIX)
+            .and_then(|hex_digest| hex::decode(hex_digest).ok())
+        else {
+            return Ok(false);
+        };
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(webhook_secret.as_bytes())?;
+        mac.update(body);
+        Ok(mac.verify_slice(&received).is_ok())
+    }
+
+    /// The page with `id`, `None` if it was removed.
+    pub async fn page(&self, id: &str) -> Result<Option<ConfluencePage>> {
+        self.pages.page(id).await
+    }
+
+    /// The ID of the page in a custom data source, see [`pages::page_url`].
+    pub fn document_id(&self, page_id: &str) -> Result<String> {
+        let base_url = self
+            .base_url
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", CONFLUENCE_BASE_URL))?;
+        Ok(pages::page_url(base_url, page_id))
+    }
+}
@@ -0,0 +1,386 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    config::Config,
+    env_keys::{CONFLUENCE_API_TOKEN, CONFLUENCE_BASE_URL, CONFLUENCE_EMAIL},
+    secrets::Secret,
+    service::bedrock_service::data_sync::CustomDocument,
+};
+
+// https://developer.atlassian.com/cloud/confluence/rest/v2/api-group-page/#api-pages-id-get
+const CURRENT_STATUS: &str = "current";
+// the tags ending a line of the text.
+const BLOCK_TAGS: [&str; 16] = [
+    "p",
+    "br",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "table",
+    "ul",
+    "ol",
+    "blockquote",
+];
+
+/// A Confluence page, as it is ingested into a custom data source.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConfluencePage {
+    pub id: String,
+    pub title: String,
+    /// See [`page_url`].
+    pub url: String,
+    /// Of the body, without the markup.
+    pub text: String,
+}
+
+impl ConfluencePage {
+    /// The page as a document of a custom data source, its URL as the ID so that it is linked from the answers.
+    pub fn document(&self) -> CustomDocument {
+        CustomDocument {
+            id: self.url.to_owned(),
+            title: Some(self.title.to_owned()),
+            text: format!("{}\n\n{}", self.title, self.text),
+        }
+    }
+}
+
+/// The link to the page by its ID, which still works once the page is moved or renamed.
+pub fn page_url(base_url: &str, id: &str) -> String {
+    format!("{}/wiki/pages/viewpage.action?pageId={}", base_url, id)
+}
+
+/// Fetches the pages the webhooks are about.
+#[async_trait]
+pub trait ConfluencePages: Debug + Send + Sync {
+    /// Fetch what fetching needs, ex: the API token, so that a missing one fails at cold start.
+    async fn load_secrets(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The current version of the page, `None` if it is removed or in the trash.
+    async fn page(&self, id: &str) -> Result<Option<ConfluencePage>>;
+}
+
+#[derive(Debug, Deserialize)]
+struct PageResponse {
+    id: String,
+    status: String,
+    title: String,
+    body: Option<PageBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageBody {
+    storage: Option<StorageBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StorageBody {
+    value: String,
+}
+
+/// Fetches the pages with the Confluence Cloud REST API, as the account of the API token.
+#[derive(Debug, Clone)]
+pub struct ConfluenceApiClient {
+    client: Client,
+    base_url: Option<String>,
+    email: Option<String>,
+    api_token: Secret,
+}
+
+impl ConfluenceApiClient {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: config.confluence_base_url.to_owned(),
+            email: config.confluence_email.to_owned(),
+            api_token: Secret::new(
+                CONFLUENCE_API_TOKEN,
+                config.confluence_api_token_source(),
+                config.secrets_cache_ttl(),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl ConfluencePages for ConfluenceApiClient {
+    async fn load_secrets(&self) -> Result<()> {
+        if self.api_token.is_configured() {
+            self.api_token.get().await?;
+        }
+        Ok(())
+    }
+
+    async fn page(&self, id: &str) -> Result<Option<ConfluencePage>> {
+        let base_url = self
+            .base_url
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", CONFLUENCE_BASE_URL))?;
+        let email = self
+            .email
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", CONFLUENCE_EMAIL))?;
+        let api_token = self.api_token.get().await?;
+
+        let response = self
+            .client
+            .get(format!(
+                "{}/wiki/api/v2/pages/{}?body-format=storage",
+                base_url, id
+            ))
+            .basic_auth(email, Some(api_token))
+            .send()
+            .await
+            .with_context(|| format!("error fetching page {}", id))?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body_string = response.text().await?;
+        if !status.is_success() {
+            bail!("error fetching page {}: {} {}", id, status, body_string);
+        }
+
+        let page: PageResponse = serde_json::from_str(&body_string)
+            .with_context(|| format!("error parsing page {}", id))?;
+        if page.status != CURRENT_STATUS {
+            println!("page {} is {}.", id, page.status);
+            return Ok(None);
+        }
+        let storage = page
+            .body
+            .and_then(|body| body.storage)
+            .map(|storage| storage.value)
+            .unwrap_or_default();
+
+        Ok(Some(ConfluencePage {
+            url: page_url(base_url, &page.id),
+            id: page.id,
+            title: page.title,
+            text: storage_to_text(&storage),
+        }))
+    }
+}
+
+/// The text of a page in the storage format (XHTML), one line per paragraph, list item, heading or table row.
+/// The new lines of the markup are spaces, but the lines of the code blocks are kept,
+/// with their whitespace collapsed like the rest. An unterminated tag is kept as text.
+pub fn storage_to_text(storage: &str) -> String {
+    let mut text = String::new();
+    let mut rest = storage;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&markup_text(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            text.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or_default();
+            continue;
+        }
+
+        let Some(end) = rest.find('>').map(|end| end + 1) else {
+            break;
+        };
+        let name: String = rest[1..end]
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push('\n');
+        } else if name == "td" || name == "th" {
+            text.push(' ');
+        }
+        rest = &rest[end..];
+    }
+    text.push_str(&markup_text(rest));
+
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+// the text between the tags, on the line of its block.
+fn markup_text(text: &str) -> String {
+    decode_entities(text).replace(['\r', '\n'], " ")
+}
+
+// the named entities of the storage format, and the numeric ones.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Keeps the pages in memory, for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryConfluencePages {
+    pages: Arc<Mutex<HashMap<String, ConfluencePage>>>,
+}
+
+impl InMemoryConfluencePages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_page(&self, page: ConfluencePage) {
+        if let Ok(mut pages) = self.pages.lock() {
+            pages.insert(page.id.to_owned(), page);
+        }
+    }
+
+    pub fn remove_page(&self, id: &str) {
+        if let Ok(mut pages) = self.pages.lock() {
+            pages.remove(id);
+        }
+    }
+}
+
+#[async_trait]
+impl ConfluencePages for InMemoryConfluencePages {
+    async fn page(&self, id: &str) -> Result<Option<ConfluencePage>> {
+        let pages = self
+            .pages
+            .lock()
+            .map_err(|_| anyhow!("confluence pages lock poisoned."))?;
+        Ok(pages.get(id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paragraphs_list_items_and_rows_are_lines() {
+        let storage = "<h1>Deploy</h1><p>Run the <strong>deploy</strong> job.</p>\
+            <ul><li>build</li><li>release</li></ul>\
+            <table><tbody><tr><th>Env</th><th>Region</th></tr>\
+            <tr><td>prod</td><td>us-east-1</td></tr></tbody></table>";
+        assert_eq!(
+            storage_to_text(storage),
+            "Deploy\nRun the deploy job.\nbuild\nrelease\nEnv Region\nprod us-east-1"
+        );
+    }
+
+    #[test]
+    fn whitespace_is_collapsed() {
+        assert_eq!(
+            storage_to_text("<p>  a \n  b  </p><br/><p></p><P>c</P>"),
+            "a b\nc"
+        );
+    }
+
+    #[test]
+    fn code_blocks_keep_their_text() {
+        let storage = "<p>Run:</p><ac:structured-macro ac:name=\"code\">\
+            <ac:plain-text-body><![CDATA[make deploy <env> && echo done]]></ac:plain-text-body>\
+            </ac:structured-macro>";
+        assert_eq!(
+            storage_to_text(storage),
+            "Run:\nmake deploy <env> && echo done"
+        );
+    }
+
+    #[test]
+    fn code_blocks_keep_their_lines() {
+        let storage =
+            "<ac:plain-text-body><![CDATA[make build\nmake   deploy]]></ac:plain-text-body>";
+        assert_eq!(storage_to_text(storage), "make build\nmake deploy");
+    }
+
+    #[test]
+    fn unterminated_cdata_keeps_the_rest() {
+        assert_eq!(storage_to_text("<p>a</p><![CDATA[b < c"), "a\nb < c");
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            storage_to_text(
+                "<p>Tom &amp; Jerry&#x27;s&nbsp;show &lt;b&gt; &quot;x&quot; &#233;</p>"
+            ),
+            "Tom & Jerry's show <b> \"x\" é"
+        );
+    }
+
+    #[test]
+    fn bare_ampersands_are_kept() {
+        assert_eq!(
+            storage_to_text("<p>R&D & QA &unknown; &#xZZ;</p>"),
+            "R&D & QA &unknown; &#xZZ;"
+        );
+    }
+
+    #[test]
+    fn unterminated_tag_is_kept_as_text() {
+        assert_eq!(
+            storage_to_text("<p>before</p><p class=\"x\""),
+            "before\n<p class=\"x\""
+        );
+        assert_eq!(storage_to_text("a < b"), "a < b");
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use crate::{config::Config, service::bedrock_service::RetrievalResult};
+
+const ANSWER_ID_ATTRIBUTE: &str = "answer_id";
+const QUESTION_ATTRIBUTE: &str = "question";
+const ANSWER_ATTRIBUTE: &str = "answer";
+const REFERENCE_URLS_ATTRIBUTE: &str = "reference_urls";
+const MODEL_ID_ATTRIBUTE: &str = "model_id";
+const KNOWLEDGE_BASE_ID_ATTRIBUTE: &str = "knowledge_base_id";
+const CHANNEL_ID_ATTRIBUTE: &str = "channel_id";
+const CREATED_AT_ATTRIBUTE: &str = "created_at";
+const RATINGS_ATTRIBUTE: &str = "ratings";
+const COMMENTS_ATTRIBUTE: &str = "comments";
+
+/// An answer posted to Slack, kept so that the ratings it receives can be judged against it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AnswerRecord {
+    pub answer_id: String,
+    pub question: String,
+    pub answer: String,
+    pub reference_urls: Vec<String>,
+    pub model_id: String,
+    pub knowledge_base_id: String,
+    pub channel_id: String,
+    pub created_at: i64,
+}
+
+impl AnswerRecord {
+    pub fn new(
+        question: &str,
+        channel_id: &str,
+        result: &RetrievalResult,
+        config: &Config,
+    ) -> Self {
+        Self {
+            answer_id: uuid::Uuid::new_v4().to_string(),
+            question: question.to_owned(),
+            answer: result.text.to_owned(),
+            reference_urls: result.reference_urls(),
+            model_id: config.chat_model_id.to_owned().unwrap_or_default(),
+            knowledge_base_id: config.knowledge_base_id.to_owned().unwrap_or_default(),
+            channel_id: channel_id.to_owned(),
+            created_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Positive,
+    Negative,
+}
+
+impl Rating {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rating::Positive => "positive",
+            Rating::Negative => "negative",
+        }
+    }
+}
+
+/// Stores answers together with the ratings and comments users give them, one per user.
+#[async_trait]
+pub trait FeedbackStore: Debug + Send + Sync {
+    async fn put_answer(&self, record: &AnswerRecord) -> Result<()>;
+    async fn put_rating(&self, answer_id: &str, user_id: &str, rating: Rating) -> Result<()>;
+    async fn put_comment(&self, answer_id: &str, user_id: &str, comment: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamoFeedbackStore {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+}
+
+impl DynamoFeedbackStore {
+    pub fn new(client: &aws_sdk_dynamodb::Client, table_name: &str) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: table_name.to_owned(),
+        }
+    }
+
+    // set `<map_attribute>.<user_id>` of an existing answer.
+    async fn set_user_value(
+        &self,
+        answer_id: &str,
+        map_attribute: &str,
+        user_id: &str,
+        value: &str,
+    ) -> Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(ANSWER_ID_ATTRIBUTE, AttributeValue::S(answer_id.to_owned()))
+            .update_expression("SET #map.#user = :value")
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#map", map_attribute)
+            .expression_attribute_names("#user", user_id)
+            .expression_attribute_names("#id", ANSWER_ID_ATTRIBUTE)
+            .expression_attribute_values(":value", AttributeValue::S(value.to_owned()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FeedbackStore for DynamoFeedbackStore {
+    async fn put_answer(&self, record: &AnswerRecord) -> Result<()> {
+        let reference_urls = record
+            .reference_urls
+            .iter()
+            .map(|url| AttributeValue::S(url.to_owned()))
+            .collect();
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .item(
+                ANSWER_ID_ATTRIBUTE,
+                AttributeValue::S(record.answer_id.to_owned()),
+            )
+            .item(
+                QUESTION_ATTRIBUTE,
+                AttributeValue::S(record.question.to_owned()),
+            )
+            .item(
+                ANSWER_ATTRIBUTE,
+                AttributeValue::S(record.answer.to_owned()),
+            )
+            .item(REFERENCE_URLS_ATTRIBUTE, AttributeValue::L(reference_urls))
+            .item(
+                MODEL_ID_ATTRIBUTE,
+                AttributeValue::S(record.model_id.to_owned()),
+            )
+            .item(
+                KNOWLEDGE_BASE_ID_ATTRIBUTE,
+                AttributeValue::S(record.knowledge_base_id.to_owned()),
+            )
+            .item(
+                CHANNEL_ID_ATTRIBUTE,
+                AttributeValue::S(record.channel_id.to_owned()),
+            )
+            .item(
+                CREATED_AT_ATTRIBUTE,
+                AttributeValue::N(record.created_at.to_string()),
+            )
+            .item(RATINGS_ATTRIBUTE, AttributeValue::M(HashMap::new()))
+            .item(COMMENTS_ATTRIBUTE, AttributeValue::M(HashMap::new()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn put_rating(&self, answer_id: &str, user_id: &str, rating: Rating) -> Result<()> {
+        self.set_user_value(answer_id, RATINGS_ATTRIBUTE, user_id, rating.as_str())
+            .await
+    }
+
+    async fn put_comment(&self, answer_id: &str, user_id: &str, comment: &str) -> Result<()> {
+        self.set_user_value(answer_id, COMMENTS_ATTRIBUTE, user_id, comment)
+            .await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackRecord {
+    pub answer: AnswerRecord,
+    pub ratings: HashMap<String, Rating>,
+    pub comments: HashMap<String, String>,
+}
+
+/// Keeps feedback in memory for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryFeedbackStore {
+    records: Arc<Mutex<HashMap<String, FeedbackRecord>>>,
+}
+
+impl InMemoryFeedbackStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, answer_id: &str) -> Option<FeedbackRecord> {
+        self.records.lock().ok()?.get(answer_id).cloned()
+    }
+
+    fn update(&self, answer_id: &str, update: impl FnOnce(&mut FeedbackRecord)) -> Result<()> {
+        let mut records = self
+            .records
+            .lock()
+            .map_err(|_| anyhow!("feedback store lock poisoned."))?;
+        let record = records
+            .get_mut(answer_id)
+            .ok_or_else(|| anyhow!("no answer with id {}.", answer_id))?;
+        update(record);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FeedbackStore for InMemoryFeedbackStore {
+    async fn put_answer(&self, record: &AnswerRecord) -> Result<()> {
+        let mut records = self
+            .records
+            .lock()
+            .map_err(|_| anyhow!("feedback store lock poisoned."))?;
+        records.insert(
+            record.answer_id.to_owned(),
+            FeedbackRecord {
+                answer: record.to_owned(),
+                ratings: HashMap::new(),
+                comments: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn put_rating(&self, answer_id: &str, user_id: &str, rating: Rating) -> Result<()> {
+        self.update(answer_id, |record| {
+            record.ratings.insert(user_id.to_owned(), rating);
+        })
+    }
+
+    async fn put_comment(&self, answer_id: &str, user_id: &str, comment: &str) -> Result<()> {
+        self.update(answer_id, |record| {
+            record
+                .comments
+                .insert(user_id.to_owned(), comment.to_owned());
+        })
+    }
+}
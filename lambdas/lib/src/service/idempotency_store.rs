@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::Utc;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const IDEMPOTENCY_KEY_ATTRIBUTE: &str = "idempotency_key";
+const STATUS_ATTRIBUTE: &str = "status";
+const LEASE_EXPIRES_AT_ATTRIBUTE: &str = "lease_expires_at";
+const EXPIRES_AT_ATTRIBUTE: &str = "expires_at";
+
+const RECEIVED_STATUS: &str = "received";
+const PROCESSING_STATUS: &str = "processing";
+const COMPLETED_STATUS: &str = "completed";
+
+// longer than the SQS message retention (4 days by default),
+// so that a redelivered message is always recognized.
+const RECORD_TTL_SECONDS: i64 = 60 * 60 * 24 * 7;
+
+/// Keeps track of the Slack events (`event_id`) and slash commands (`trigger_id`) we have seen,
+/// so that each of them is queued and answered only once
+/// even when Slack retries or SQS redelivers them.
+#[async_trait]
+pub trait IdempotencyStore: Debug + Send + Sync {
+    /// Record that the request was received. `false` if it had already been.
+    async fn mark_received(&self, key: &str) -> Result<bool>;
+
+    /// Take the lease on answering the request for `lease`.
+    /// `false` if it has already been answered, or is being answered under a lease that has not expired.
+    async fn start_processing(&self, key: &str, lease: Duration) -> Result<bool>;
+
+    /// Record that the request has been answered. It will not be processed again.
+    async fn mark_completed(&self, key: &str) -> Result<()>;
+
+    /// Give up the lease after a failure, so that a retry can process the request right away.
+    async fn release(&self, key: &str) -> Result<()>;
+
+    /// Remove the record, ex: when the request could not be queued and should be accepted again on retry.
+    async fn forget(&self, key: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamoIdempotencyStore {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+}
+
+impl DynamoIdempotencyStore {
+    pub fn new(client: &aws_sdk_dynamodb::Client, table_name: &str) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: table_name.to_owned(),
+        }
+    }
+
+    // the item might not exist anymore, ex: once expired, and is then created with its own TTL.
+    async fn set_status(&self, key: &str, status: &str, lease_expires_at: i64) -> Result<()> {
+        let expires_at = Utc::now().timestamp() + RECORD_TTL_SECONDS;
+
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(IDEMPOTENCY_KEY_ATTRIBUTE, AttributeValue::S(key.to_owned()))
+            .update_expression(
+                "SET #status = :status, #lease = :lease, #expires = if_not_exists(#expires, :expires)",
+            )
+            .expression_attribute_names("#status", STATUS_ATTRIBUTE)
+            .expression_attribute_names("#lease", LEASE_EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_names("#expires", EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_values(":status", AttributeValue::S(status.to_owned()))
+            .expression_attribute_values(":lease", AttributeValue::N(lease_expires_at.to_string()))
+            .expression_attribute_values(":expires", AttributeValue::N(expires_at.to_string()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for DynamoIdempotencyStore {
+    async fn mark_received(&self, key: &str) -> Result<bool> {
+        let expires_at = Utc::now().timestamp() + RECORD_TTL_SECONDS;
+
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item(IDEMPOTENCY_KEY_ATTRIBUTE, AttributeValue::S(key.to_owned()))
+            .item(
+                STATUS_ATTRIBUTE,
+                AttributeValue::S(RECEIVED_STATUS.to_owned()),
+            )
+            .item(
+                LEASE_EXPIRES_AT_ATTRIBUTE,
+                AttributeValue::N("0".to_owned()),
+            )
+            .item(
+                EXPIRES_AT_ATTRIBUTE,
+                AttributeValue::N(expires_at.to_string()),
+            )
+            .condition_expression("attribute_not_exists(#key)")
+            .expression_attribute_names("#key", IDEMPOTENCY_KEY_ATTRIBUTE)
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(error)
+                if error
+                    .as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn start_processing(&self, key: &str, lease: Duration) -> Result<bool> {
+        let now = Utc::now().timestamp();
+        let lease_expires_at = now + lease.as_secs() as i64;
+        let expires_at = now + RECORD_TTL_SECONDS;
+
+        // the item might not exist yet if the receive handler failed to record it.
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(IDEMPOTENCY_KEY_ATTRIBUTE, AttributeValue::S(key.to_owned()))
+            .update_expression(
+                "SET #status = :processing, #lease = :lease, #expires = if_not_exists(#expires, :expires)",
+            )
+            .condition_expression(
+                "attribute_not_exists(#key) OR #status = :received OR (#status = :processing AND #lease < :now)",
+            )
+            .expression_attribute_names("#key", IDEMPOTENCY_KEY_ATTRIBUTE)
+            .expression_attribute_names("#status", STATUS_ATTRIBUTE)
+            .expression_attribute_names("#lease", LEASE_EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_names("#expires", EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_values(
+                ":processing",
+                AttributeValue::S(PROCESSING_STATUS.to_owned()),
+            )
+            .expression_attribute_values(
+                ":received",
+                AttributeValue::S(RECEIVED_STATUS.to_owned()),
+            )
+            .expression_attribute_values(":lease", AttributeValue::N(lease_expires_at.to_string()))
+            .expression_attribute_values(":expires", AttributeValue::N(expires_at.to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(error)
+                if error
+                    .as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn mark_completed(&self, key: &str) -> Result<()> {
+        self.set_status(key, COMPLETED_STATUS, 0).await
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.set_status(key, RECEIVED_STATUS, 0).await
+    }
+
+    async fn forget(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(IDEMPOTENCY_KEY_ATTRIBUTE, AttributeValue::S(key.to_owned()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum InMemoryStatus {
+    Received,
+    Processing { lease_expires_at: i64 },
+    Completed,
+}
+
+/// Keeps the records in memory for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryIdempotencyStore {
+    records: Arc<Mutex<HashMap<String, InMemoryStatus>>>,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn records(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, InMemoryStatus>>> {
+        self.records
+            .lock()
+            .map_err(|_| anyhow!("idempotency store lock poisoned."))
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn mark_received(&self, key: &str) -> Result<bool> {
+        let mut records = self.records()?;
+        if records.contains_key(key) {
+            return Ok(false);
+        }
+        records.insert(key.to_owned(), InMemoryStatus::Received);
+        Ok(true)
+    }
+
+    async fn start_processing(&self, key: &str, lease: Duration) -> Result<bool> {
+        let now = Utc::now().timestamp();
+        let mut records = self.records()?;
+        let available = match records.get(key) {
+            None | Some(InMemoryStatus::Received) => true,
+            Some(InMemoryStatus::Processing { lease_expires_at }) => *lease_expires_at < now,
+            Some(InMemoryStatus::Completed) => false,
+        };
+        if available {
+            records.insert(
+                key.to_owned(),
+                InMemoryStatus::Processing {
+                    lease_expires_at: now + lease.as_secs() as i64,
+                },
+            );
+        }
+        Ok(available)
+    }
+
+    async fn mark_completed(&self, key: &str) -> Result<()> {
+        self.records()?
+            .insert(key.to_owned(), InMemoryStatus::Completed);
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.records()?
+            .insert(key.to_owned(), InMemoryStatus::Received);
+        Ok(())
+    }
+
+    async fn forget(&self, key: &str) -> Result<()> {
+        self.records()?.remove(key);
+        Ok(())
+    }
+}
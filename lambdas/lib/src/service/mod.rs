@@ -1,28 +1,144 @@
 pub mod bedrock_service;
+pub mod confluence_service;
+pub mod feedback_store;
+pub mod idempotency_store;
+pub mod s3_presigner;
+pub mod session_store;
 pub mod slack_service;
 pub mod sqs_service;
+pub mod sync_workflow;
+
+use anyhow::Result;
+use std::sync::Arc;
 
 use aws_config::SdkConfig;
 
+use crate::config::Config;
+
 #[derive(Debug, Clone)]
 pub struct CommonService {
-    pub bedrock: bedrock_service::BedrockService,
-    pub sqs: sqs_service::SQSService,
+    pub config: Config,
+    pub retriever: Arc<dyn bedrock_service::retriever::KnowledgeRetriever>,
+    pub data_sync: Arc<dyn bedrock_service::data_sync::KnowledgeBaseSync>,
+    pub sync_workflow: Arc<dyn sync_workflow::SyncWorkflow>,
+    pub queue: Arc<dyn sqs_service::EventQueue>,
     pub slack: slack_service::SlackService,
+    pub confluence: confluence_service::ConfluenceService,
+    pub session_store: Arc<dyn session_store::SessionStore>,
+    pub feedback_store: Arc<dyn feedback_store::FeedbackStore>,
+    pub idempotency_store: Arc<dyn idempotency_store::IdempotencyStore>,
 }
 
 impl CommonService {
-    pub fn new(config: &SdkConfig) -> Self {
-        let bedrock_runtime_client = aws_sdk_bedrockagentruntime::Client::new(&config);
-        let bedrock_client = aws_sdk_bedrockagent::Client::new(&config);
-        let sqs_client = aws_sdk_sqs::Client::new(&config);
+    /// Load the [`Config`] from the environment, failing if any of the `required` keys is missing
+    /// so that a misconfigured lambda fails at cold start rather than on the first request.
+    /// The Slack and Confluence secrets are fetched here as well.
+    pub async fn new(sdk_config: &SdkConfig, required: &[&str]) -> Result<Self> {
+        let config = Config::from_env()?;
+        config.require(required)?;
+        let service = Self::with_config(sdk_config, config);
+        service.slack.load_secrets().await?;
+        service.confluence.load_secrets().await?;
+        Ok(service)
+    }
+
+    /// Production services for `config`.
+    /// In tests, use [`InMemoryServices::service`] instead.
+    pub fn with_config(sdk_config: &SdkConfig, config: Config) -> Self {
+        let bedrock_runtime_client = aws_sdk_bedrockagentruntime::Client::new(sdk_config);
+        let bedrock_client = aws_sdk_bedrockagent::Client::new(sdk_config);
+        let sqs_client = aws_sdk_sqs::Client::new(sdk_config);
+        let dynamodb_client = aws_sdk_dynamodb::Client::new(sdk_config);
 
-        let line_client = slack_service::SlackService::new();
+        let line_client = slack_service::SlackService::new(&config);
+
+        // fall back to in-memory stores (per Lambda container) when no table is configured, ex: local runs.
+        let session_store: Arc<dyn session_store::SessionStore> = match &config.session_table_name {
+            Some(table_name) => Arc::new(session_store::DynamoSessionStore::new(
+                &dynamodb_client,
+                table_name,
+            )),
+            None => Arc::new(session_store::InMemorySessionStore::new()),
+        };
+        let feedback_store: Arc<dyn feedback_store::FeedbackStore> =
+            match &config.feedback_table_name {
+                Some(table_name) => Arc::new(feedback_store::DynamoFeedbackStore::new(
+                    &dynamodb_client,
+                    table_name,
+                )),
+                None => Arc::new(feedback_store::InMemoryFeedbackStore::new()),
+            };
+        let idempotency_store: Arc<dyn idempotency_store::IdempotencyStore> =
+            match &config.idempotency_table_name {
+                Some(table_name) => Arc::new(idempotency_store::DynamoIdempotencyStore::new(
+                    &dynamodb_client,
+                    table_name,
+                )),
+                None => Arc::new(idempotency_store::InMemoryIdempotencyStore::new()),
+            };
+
+        let presigner = s3_presigner::S3Presigner::new(sdk_config, &config);
+        let bedrock = Arc::new(bedrock_service::BedrockService::new(
+            &bedrock_runtime_client,
+            &bedrock_client,
+            &presigner,
+            &config,
+        ));
 
         Self {
-            bedrock: bedrock_service::BedrockService::new(&bedrock_runtime_client, &bedrock_client),
-            sqs: sqs_service::SQSService::new(&sqs_client),
+            retriever: bedrock.clone(),
+            data_sync: bedrock,
+            sync_workflow: Arc::new(sync_workflow::StepFunctionsSyncWorkflow::new(
+                sdk_config, &config,
+            )),
+            queue: Arc::new(sqs_service::SQSService::new(&sqs_client)),
             slack: line_client,
+            confluence: confluence_service::ConfluenceService::new(&config),
+            session_store,
+            feedback_store,
+            idempotency_store,
+            config,
+        }
+    }
+}
+
+/// The in-memory version of every service, for tests and local runs.
+/// Clones share their state, so keep these to set the services up and check what they were asked,
+/// ex: `services.poster.calls()` after handling an event with `services.service(config)`.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryServices {
+    pub retriever: bedrock_service::retriever::InMemoryKnowledgeRetriever,
+    pub data_sync: bedrock_service::data_sync::InMemoryKnowledgeBaseSync,
+    pub sync_workflow: sync_workflow::InMemorySyncWorkflow,
+    pub queue: sqs_service::InMemoryEventQueue,
+    pub poster: slack_service::poster::InMemoryChatPoster,
+    pub pages: confluence_service::pages::InMemoryConfluencePages,
+    pub session_store: session_store::InMemorySessionStore,
+    pub feedback_store: feedback_store::InMemoryFeedbackStore,
+    pub idempotency_store: idempotency_store::InMemoryIdempotencyStore,
+}
+
+impl InMemoryServices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Services for `config` backed by these.
+    pub fn service(&self, config: Config) -> CommonService {
+        CommonService {
+            retriever: Arc::new(self.retriever.clone()),
+            data_sync: Arc::new(self.data_sync.clone()),
+            sync_workflow: Arc::new(self.sync_workflow.clone()),
+            queue: Arc::new(self.queue.clone()),
+            slack: slack_service::SlackService::with_poster(&config, Arc::new(self.poster.clone())),
+            confluence: confluence_service::ConfluenceService::with_pages(
+                &config,
+                Arc::new(self.pages.clone()),
+            ),
+            session_store: Arc::new(self.session_store.clone()),
+            feedback_store: Arc::new(self.feedback_store.clone()),
+            idempotency_store: Arc::new(self.idempotency_store.clone()),
+            config,
         }
     }
 }
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::Utc;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+const CONVERSATION_KEY_ATTRIBUTE: &str = "conversation_key";
+const SESSION_ID_ATTRIBUTE: &str = "session_id";
+const EXPIRES_AT_ATTRIBUTE: &str = "expires_at";
+
+// Bedrock drops idle retrieve_and_generate sessions after 24 hours,
+// so there is no point keeping the mapping around for longer than that.
+const SESSION_TTL_SECONDS: i64 = 60 * 60 * 24;
+
+/// Maps a Slack conversation, identified by `(channel, thread_ts)`,
+/// to the Bedrock session ID used to answer questions in it.
+#[async_trait]
+pub trait SessionStore: Debug + Send + Sync {
+    async fn get(&self, channel_id: &str, thread_ts: &str) -> Result<Option<String>>;
+    async fn put(&self, channel_id: &str, thread_ts: &str, session_id: &str) -> Result<()>;
+}
+
+fn conversation_key(channel_id: &str, thread_ts: &str) -> String {
+    format!("{}:{}", channel_id, thread_ts)
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamoSessionStore {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+}
+
+impl DynamoSessionStore {
+    pub fn new(client: &aws_sdk_dynamodb::Client, table_name: &str) -> Self {
+        Self {
+            client: client.to_owned(),
+            table_name: table_name.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for DynamoSessionStore {
+    async fn get(&self, channel_id: &str, thread_ts: &str) -> Result<Option<String>> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key(
+                CONVERSATION_KEY_ATTRIBUTE,
+                AttributeValue::S(conversation_key(channel_id, thread_ts)),
+            )
+            .send()
+            .await?;
+
+        let Some(item) = response.item() else {
+            return Ok(None);
+        };
+
+        // DynamoDB TTL deletion is lazy, so expired items might still be returned.
+        let expires_at = item
+            .get(EXPIRES_AT_ATTRIBUTE)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<i64>().ok());
+        if expires_at.is_some_and(|e| e <= Utc::now().timestamp()) {
+            return Ok(None);
+        }
+
+        let session_id = item
+            .get(SESSION_ID_ATTRIBUTE)
+            .and_then(|v| v.as_s().ok())
+            .context("session item without session id.")?;
+
+        Ok(Some(session_id.to_owned()))
+    }
+
+    async fn put(&self, channel_id: &str, thread_ts: &str, session_id: &str) -> Result<()> {
+        let expires_at = Utc::now().timestamp() + SESSION_TTL_SECONDS;
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .item(
+                CONVERSATION_KEY_ATTRIBUTE,
+                AttributeValue::S(conversation_key(channel_id, thread_ts)),
+            )
+            .item(
+                SESSION_ID_ATTRIBUTE,
+                AttributeValue::S(session_id.to_owned()),
+            )
+            .item(
+                EXPIRES_AT_ATTRIBUTE,
+                AttributeValue::N(expires_at.to_string()),
+            )
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps sessions in memory for tests and local runs.
+/// Sessions only live as long as the process.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySessionStore {
+    sessions: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn get(&self, channel_id: &str, thread_ts: &str) -> Result<Option<String>> {
+        let sessions = self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("session store lock poisoned."))?;
+        Ok(sessions
+            .get(&conversation_key(channel_id, thread_ts))
+            .cloned())
+    }
+
+    async fn put(&self, channel_id: &str, thread_ts: &str, session_id: &str) -> Result<()> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("session store lock poisoned."))?;
+        sessions.insert(
+            conversation_key(channel_id, thread_ts),
+            session_id.to_owned(),
+        );
+        Ok(())
+    }
+}
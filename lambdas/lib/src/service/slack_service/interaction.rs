@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const BLOCK_ACTIONS_TYPE: &str = "block_actions";
+pub const VIEW_SUBMISSION_TYPE: &str = "view_submission";
+
+pub const FEEDBACK_BLOCK_ID: &str = "feedback";
+pub const POSITIVE_FEEDBACK_ACTION_ID: &str = "feedback_positive";
+pub const NEGATIVE_FEEDBACK_ACTION_ID: &str = "feedback_negative";
+pub const FEEDBACK_MODAL_CALLBACK_ID: &str = "feedback_comment";
+const COMMENT_BLOCK_ID: &str = "comment";
+const COMMENT_ACTION_ID: &str = "comment_input";
+
+/// https://api.slack.com/interactivity/handling#payloads
+/// Sent as `application/x-www-form-urlencoded` with the JSON in the `payload` field.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InteractionRequest {
+    pub payload: String,
+}
+
+/// https://api.slack.com/reference/interaction-payloads
+/// Only the fields of `block_actions` and `view_submission` payloads we use.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InteractionPayload {
+    pub r#type: String,
+    pub user: InteractionUser,
+    #[serde(default)]
+    pub trigger_id: Option<String>,
+    #[serde(default)]
+    pub response_url: Option<String>,
+    #[serde(default)]
+    pub actions: Vec<BlockAction>,
+    #[serde(default)]
+    pub view: Option<ViewPayload>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InteractionUser {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BlockAction {
+    pub action_id: String,
+    #[serde(default)]
+    pub block_id: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ViewPayload {
+    #[serde(default)]
+    pub callback_id: String,
+    #[serde(default)]
+    pub private_metadata: String,
+    #[serde(default)]
+    pub state: ViewState,
+}
+
+/// Input values of the view, `block_id` -> `action_id` -> value.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ViewState {
+    #[serde(default)]
+    pub values: HashMap<String, HashMap<String, ViewStateValue>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ViewStateValue {
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+impl ViewPayload {
+    /// The comment entered in the feedback modal, if any.
+    pub fn feedback_comment(&self) -> Option<&str> {
+        self.state
+            .values
+            .get(COMMENT_BLOCK_ID)
+            .and_then(|block| block.get(COMMENT_ACTION_ID))
+            .and_then(|v| v.value.as_deref())
+            .map(|comment| comment.trim())
+            .filter(|comment| !comment.is_empty())
+    }
+}
+
+/// 👍/👎 buttons carrying the id of the answer they rate.
+pub(crate) fn feedback_block(answer_id: &str) -> Value {
+    json!({
+        "type": "actions",
+        "block_id": FEEDBACK_BLOCK_ID,
+        "elements": [
+            {
+                "type": "button",
+                "action_id": POSITIVE_FEEDBACK_ACTION_ID,
+                "text": { "type": "plain_text", "text": "👍", "emoji": true },
+                "value": answer_id
+            },
+            {
+                "type": "button",
+                "action_id": NEGATIVE_FEEDBACK_ACTION_ID,
+                "text": { "type": "plain_text", "text": "👎", "emoji": true },
+                "value": answer_id
+            }
+        ]
+    })
+}
+
+/// Modal asking what was wrong with the answer. Submitting it is optional.
+pub(crate) fn feedback_modal(answer_id: &str) -> Value {
+    json!({
+        "type": "modal",
+        "callback_id": FEEDBACK_MODAL_CALLBACK_ID,
+        "private_metadata": answer_id,
+        "title": { "type": "plain_text", "text": "Thanks for the feedback" },
+        "submit": { "type": "plain_text", "text": "Send" },
+        "close": { "type": "plain_text", "text": "Skip" },
+        "blocks": [
+            {
+                "type": "input",
+                "block_id": COMMENT_BLOCK_ID,
+                "optional": true,
+                "label": { "type": "plain_text", "text": "What was wrong?" },
+                "element": {
+                    "type": "plain_text_input",
+                    "action_id": COMMENT_ACTION_ID,
+                    "multiline": true
+                }
+            }
+        ]
+    })
+}
@@ -0,0 +1,897 @@
+use anyhow::Result;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use mrkdwn::markdown_to_mrkdwn;
+use poster::{ChatPoster, SlackApiClient};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use split::{split_text, SECTION_TEXT_LIMIT};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    env_keys::SLACK_SIGNING_SECRET,
+    error::AnswerError,
+    secrets::Secret,
+    service::bedrock_service::{
+        citation::Citation,
+        data_sync::{DataSyncRun, SyncReply},
+        RetrievalResult, SearchMatch,
+    },
+};
+
+pub mod interaction;
+pub mod mrkdwn;
+pub mod poster;
+pub mod split;
+
+pub const EVENT_CALLBACK_TYPE: &str = "event_callback";
+pub const URL_VERIFICATION_TYPE: &str = "url_verification";
+pub const APP_MENTION_EVENT_TYPE: &str = "app_mention";
+pub const MESSAGE_EVENT_TYPE: &str = "message";
+pub const IM_CHANNEL_TYPE: &str = "im";
+pub const ASK_CONFLUENCE_COMMAND: &str = "/ask-confluence";
+pub const PRIVATE_FLAG: &str = "--private";
+pub const FIND_FLAG: &str = "--find";
+// `@bot find ...` lists the matching pages instead of answering.
+pub const FIND_KEYWORD: &str = "find";
+// `admin sync [data source...]`
+pub const ADMIN_KEYWORD: &str = "admin";
+pub const SYNC_KEYWORD: &str = "sync";
+
+const VERSION_NUMBER: &str = "v0";
+
+const STREAMING_PLACEHOLDER_TEXT: &str = ":hourglass_flowing_sand: Looking into Confluence...";
+// chat.update is a Tier 3 method (around 50 requests per minute per workspace).
+pub const STREAMING_UPDATE_INTERVAL: Duration = Duration::from_millis(1500);
+
+// keeps a message well under Slack's 50 blocks and 40000 characters, the rest goes to follow-up messages.
+const MAX_SECTIONS_PER_MESSAGE: usize = 10;
+// https://api.slack.com/reference/block-kit/blocks#context
+const CONTEXT_ELEMENTS_LIMIT: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct SlackService {
+    poster: Arc<dyn ChatPoster>,
+    signing_secret: Secret,
+}
+
+/// Slack answered with an HTTP error status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlackHttpError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl SlackHttpError {
+    /// Rate limited (429) or a server error (5xx).
+    pub fn is_retryable(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS || self.status.is_server_error()
+    }
+}
+
+impl std::fmt::Display for SlackHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "slack responded with {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for SlackHttpError {}
+
+/// Slack answered with `{"ok": false, "error": ...}`.
+/// https://api.slack.com/web#evaluating_responses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlackApiError {
+    /// The bot is not a member of the channel.
+    NotInChannel,
+    /// The channel does not exist, or the bot cannot see it.
+    ChannelNotFound,
+    /// The message is longer than Slack accepts.
+    MessageTooLong,
+    /// Still rate limited after waiting for `Retry-After`.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    Other(String),
+}
+
+// errors on Slack's side that might be gone when tried again.
+const RETRYABLE_SLACK_ERRORS: [&str; 4] = [
+    "internal_error",
+    "fatal_error",
+    "service_unavailable",
+    "request_timeout",
+];
+
+impl SlackApiError {
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "not_in_channel" => SlackApiError::NotInChannel,
+            "channel_not_found" => SlackApiError::ChannelNotFound,
+            "msg_too_long" => SlackApiError::MessageTooLong,
+            "ratelimited" | "rate_limited" => SlackApiError::RateLimited { retry_after: None },
+            code => SlackApiError::Other(code.to_owned()),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            SlackApiError::NotInChannel => "not_in_channel",
+            SlackApiError::ChannelNotFound => "channel_not_found",
+            SlackApiError::MessageTooLong => "msg_too_long",
+            SlackApiError::RateLimited { .. } => "ratelimited",
+            SlackApiError::Other(code) => code,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SlackApiError::RateLimited { .. } => true,
+            SlackApiError::Other(code) => RETRYABLE_SLACK_ERRORS.contains(&code.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Nothing can be posted to the channel, not even an error reply.
+    pub fn is_channel_unavailable(&self) -> bool {
+        matches!(
+            self,
+            SlackApiError::NotInChannel | SlackApiError::ChannelNotFound
+        )
+    }
+}
+
+impl std::fmt::Display for SlackApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlackApiError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "slack error: ratelimited, retry after {}s",
+                retry_after.as_secs()
+            ),
+            error => write!(f, "slack error: {}", error.code()),
+        }
+    }
+}
+
+impl std::error::Error for SlackApiError {}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EventChallengeRequest {
+    pub challenge: String,
+    pub token: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MessageEventRequest {
+    pub token: String,
+    pub api_app_id: String,
+    pub r#type: String, // event_callback
+    pub event_id: String,
+    pub event_time: u64,
+    pub event: MessageEvent,
+}
+
+/// https://api.slack.com/events/app_mention
+/// ```json
+///  {
+///     "type": "app_mention",
+///     "user": "U061F7AUR",
+///     "text": "<@U0LAN0Z89> is it everything a river should be?",
+///     "ts": "1515449522.000016",
+///     "channel": "C123ABC456",
+///     "event_ts": "1515449522000016"
+/// }
+/// ```
+/// https://api.slack.com/events/message.im
+/// ```json
+///  {
+///     "type": "message",
+///     "channel": "D024BE91L",
+///     "user": "U2147483697",
+///     "text": "Hello hello can you hear me?",
+///     "ts": "1355517523.000005",
+///     "event_ts": "1355517523.000005",
+///     "channel_type": "im"
+/// }
+/// ```
+/// `thread_ts` is only present when the message is a reply in a thread.
+/// `subtype` is set for edits, deletions, etc., and `bot_id` for messages posted by bots, including this one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MessageEvent {
+    pub channel: String,
+    pub r#type: String,   // app_mention, message
+    pub event_ts: String, // thread_ts
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot_id: Option<String>,
+}
+
+/// https://api.slack.com/interactivity/slash-commands#app_command_handling
+/// Sent as `application/x-www-form-urlencoded`. Fields we don't use are ignored.
+/// ```text
+/// token=gIkuvaNzQIHg97ATvDxqgjtO
+/// &team_id=T0001
+/// &channel_id=C2147483705
+/// &user_id=U2147483697
+/// &command=/ask-confluence
+/// &text=--private how do I request a VPN account?
+/// &response_url=https://hooks.slack.com/commands/1234/5678
+/// &trigger_id=13345224609.738474920.8088930838d88f008e0
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SlashCommandRequest {
+    pub token: String,
+    pub team_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub command: String,
+    pub text: String,
+    pub response_url: String,
+    pub trigger_id: String,
+}
+
+/// Options given with the slash command text, ex: `--private how do I ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlashCommandOptions {
+    pub question: String,
+    /// Reply to the caller only.
+    pub private: bool,
+    /// Only list the matching pages, without generating an answer.
+    pub find: bool,
+}
+
+impl SlashCommandRequest {
+    pub fn options(&self) -> SlashCommandOptions {
+        let mut private = false;
+        let mut find = false;
+        let mut words = vec![];
+        for word in self.text.split_whitespace() {
+            match word {
+                PRIVATE_FLAG => private = true,
+                FIND_FLAG => find = true,
+                _ => words.push(word),
+            }
+        }
+
+        SlashCommandOptions {
+            question: words.join(" "),
+            private,
+            find,
+        }
+    }
+}
+
+/// The query of a `find ...` message, the mention removed.
+/// `None` for anything else, to be answered as a question.
+pub fn search_query(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    let (keyword, query) = text.split_once(char::is_whitespace)?;
+    if !keyword.eq_ignore_ascii_case(FIND_KEYWORD) {
+        return None;
+    }
+    Some(query.trim()).filter(|query| !query.is_empty())
+}
+
+/// The data sources of an `admin sync ...` message, the mention removed, empty for all of them.
+/// `None` for anything else.
+pub fn admin_sync_request(text: &str) -> Option<Vec<String>> {
+    let mut words = text.split_whitespace();
+    let is_admin_sync = words
+        .next()
+        .is_some_and(|w| w.eq_ignore_ascii_case(ADMIN_KEYWORD))
+        && words
+            .next()
+            .is_some_and(|w| w.eq_ignore_ascii_case(SYNC_KEYWORD));
+    if !is_admin_sync {
+        return None;
+    }
+    Some(words.map(|w| w.to_owned()).collect())
+}
+
+impl MessageEvent {
+    pub fn is_direct_message(&self) -> bool {
+        self.r#type == MESSAGE_EVENT_TYPE && self.channel_type.as_deref() == Some(IM_CHANNEL_TYPE)
+    }
+
+    /// The thread to answer in.
+    /// Mentions are answered in a thread, direct messages in the conversation itself unless sent in a thread.
+    pub fn reply_thread_ts(&self) -> Option<&str> {
+        match (&self.thread_ts, self.is_direct_message()) {
+            (Some(thread_ts), _) => Some(thread_ts),
+            (None, true) => None,
+            (None, false) => Some(&self.event_ts),
+        }
+    }
+
+    /// Identifies the conversation within the channel: the thread, or the message starting it.
+    /// A direct message outside of a thread starts its own conversation, continued by replying in its thread.
+    pub fn conversation_ts(&self) -> &str {
+        self.thread_ts.as_deref().unwrap_or(&self.event_ts)
+    }
+
+    /// The user to mention in the answer. No need to in direct messages.
+    pub fn reply_user(&self) -> Option<&str> {
+        if self.is_direct_message() {
+            None
+        } else {
+            Some(&self.user)
+        }
+    }
+}
+
+impl SlackService {
+    pub fn new(config: &Config) -> Self {
+        Self::with_poster(config, Arc::new(SlackApiClient::new(config)))
+    }
+
+    /// Send the messages through `poster`, ex: an [`poster::InMemoryChatPoster`] in tests.
+    pub fn with_poster(config: &Config, poster: Arc<dyn ChatPoster>) -> Self {
+        Self {
+            poster,
+            signing_secret: Secret::new(
+                SLACK_SIGNING_SECRET,
+                config.slack_signing_secret_source(),
+                config.secrets_cache_ttl(),
+            ),
+        }
+    }
+
+    /// Fetch the configured secrets, so that an unreachable or missing secret fails at cold start.
+    pub async fn load_secrets(&self) -> Result<()> {
+        if self.signing_secret.is_configured() {
+            self.signing_secret.get().await?;
+        }
+        self.poster.load_secrets().await
+    }
+
+    // https://api.slack.com/authentication/verifying-requests-from-slack
+    pub async fn verify_signature(
+        &self,
+        timestamp: u64,
+        request_body: &str,
+        received_signature: &str,
+    ) -> Result<bool> {
+        let signing_secret = self.signing_secret.get().await?;
+
+        let now = Utc::now().timestamp();
+        // The request timestamp is more than five minutes from local time. ignore
+        if (now - timestamp as i64).abs() > 60 * 5 {
+            return Ok(false);
+        }
+        let sig_basestring = format!("{}:{}:{}", VERSION_NUMBER, timestamp, request_body);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.as_bytes())?;
+        mac.update(sig_basestring.as_bytes());
+        let result = mac.finalize();
+        let result_bytes = result.into_bytes();
+        let hex_digest = hex::encode(result_bytes); // Converts the byte array to a hex string
+        let calculated_signature = format!("{}={}", VERSION_NUMBER, hex_digest);
+
+        Ok(calculated_signature == received_signature)
+    }
+
+    pub fn verify_url_verification_request(
+        &self,
+        event_challenge_request: &EventChallengeRequest,
+    ) -> bool {
+        event_challenge_request.r#type == URL_VERIFICATION_TYPE
+    }
+
+    pub fn verify_slash_command_request(&self, command_request: &SlashCommandRequest) -> bool {
+        command_request.command == ASK_CONFLUENCE_COMMAND
+    }
+
+    pub fn verify_message_request(&self, message_request: &MessageEventRequest) -> bool {
+        let event = &message_request.event;
+        if message_request.r#type != EVENT_CALLBACK_TYPE {
+            return false;
+        }
+        // ignore edits, deletions and any message from bots, ourselves included, to avoid answering in loops.
+        if event.subtype.is_some() || event.bot_id.is_some() || event.user.is_empty() {
+            return false;
+        }
+        event.r#type == APP_MENTION_EVENT_TYPE || event.is_direct_message()
+    }
+
+    /// Post the result in the channel, in the thread of `thread_ts` if given and mentioning `user_id` if given.
+    /// Feedback buttons are added when the answer is saved with `answer_id`.
+    /// A long answer is continued in follow-up messages.
+    pub async fn send_retrieve_result(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        user_id: Option<&str>,
+        result: &RetrievalResult,
+        answer_id: Option<&str>,
+    ) -> Result<()> {
+        for blocks in retrieve_result_messages(user_id, result, answer_id) {
+            self.post_blocks(channel_id, thread_ts, blocks).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reply to a slash command through its `response_url`.
+    /// `private` replies are only visible to the user who ran the command.
+    pub async fn send_slash_command_result(
+        &self,
+        response_url: &str,
+        private: bool,
+        user_id: &str,
+        result: &RetrievalResult,
+        answer_id: Option<&str>,
+    ) -> Result<()> {
+        for blocks in retrieve_result_messages(Some(user_id), result, answer_id) {
+            let body = json!({
+                "response_type": if private { "ephemeral" } else { "in_channel" },
+                "replace_original": false,
+                "blocks": blocks
+            });
+            self.poster.respond(response_url, &body).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Post the sources matching `query` in the channel, in the thread of `thread_ts` if given and mentioning `user_id` if given.
+    pub async fn send_search_result(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        user_id: Option<&str>,
+        query: &str,
+        matches: &[SearchMatch],
+    ) -> Result<()> {
+        self.post_blocks(
+            channel_id,
+            thread_ts,
+            search_result_blocks(user_id, query, matches),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Same as [`SlackService::send_search_result`], through the `response_url` of a slash command.
+    pub async fn send_slash_command_search_result(
+        &self,
+        response_url: &str,
+        private: bool,
+        user_id: &str,
+        query: &str,
+        matches: &[SearchMatch],
+    ) -> Result<()> {
+        let body = json!({
+            "response_type": if private { "ephemeral" } else { "in_channel" },
+            "replace_original": false,
+            "blocks": search_result_blocks(Some(user_id), query, matches)
+        });
+
+        self.poster.respond(response_url, &body).await
+    }
+
+    /// Post the outcome of a knowledge base sync, ex: to the ops channel.
+    pub async fn send_sync_summary(&self, channel_id: &str, run: &DataSyncRun) -> Result<()> {
+        self.post_blocks(
+            channel_id,
+            None,
+            text_blocks(&mrkdwn::escape(&run.summary())),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Post the progress of a sync requested from Slack, or update it once posted, returning the `ts` of the message.
+    /// The requester is mentioned in a new reply once the sync finishes.
+    pub async fn send_sync_progress(&self, reply: &SyncReply, run: &DataSyncRun) -> Result<String> {
+        let blocks = text_blocks(&mrkdwn::escape(&run.summary()));
+        let message_ts = match &reply.message_ts {
+            Some(message_ts) => {
+                self.update_message(&reply.channel_id, message_ts, blocks, true)
+                    .await?;
+                message_ts.to_owned()
+            }
+            None => {
+                self.post_blocks(&reply.channel_id, reply.thread_ts.as_deref(), blocks)
+                    .await?
+            }
+        };
+
+        if run.finished {
+            self.send_text_reply(
+                &reply.channel_id,
+                reply.thread_ts.as_deref(),
+                reply.user_id.as_deref(),
+                "The knowledge base sync finished.",
+            )
+            .await?;
+        }
+
+        Ok(message_ts)
+    }
+
+    /// Reply with a plain message, ex: when a command is not allowed.
+    pub async fn send_text_reply(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        user_id: Option<&str>,
+        text: &str,
+    ) -> Result<()> {
+        self.post_blocks(
+            channel_id,
+            thread_ts,
+            text_blocks(&with_mention(user_id, text)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether `user_id` is one of the `user_ids` or in one of the `user_group_ids`.
+    /// A user group that cannot be listed is left out.
+    pub async fn is_allowed(
+        &self,
+        user_id: &str,
+        user_ids: &[String],
+        user_group_ids: &[String],
+    ) -> bool {
+        if user_ids.iter().any(|id| id == user_id) {
+            return true;
+        }
+        for user_group_id in user_group_ids {
+            match self.poster.user_group_members(user_group_id).await {
+                Ok(members) if members.iter().any(|id| id == user_id) => return true,
+                Ok(_) => {}
+                Err(error) => println!("error listing user group {}: {}", user_group_id, error),
+            }
+        }
+        false
+    }
+
+    /// Tell the asker why the question could not be answered.
+    /// `correlation_id` is shown so that the failure can be looked up in the logs.
+    pub async fn send_error_reply(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        user_id: Option<&str>,
+        error: &AnswerError,
+        correlation_id: &str,
+    ) -> Result<()> {
+        let mut body = json!({
+            "channel": channel_id,
+            "blocks": error_blocks(user_id, error, correlation_id)
+        });
+        if let Some(thread_ts) = thread_ts {
+            body["thread_ts"] = json!(thread_ts);
+        }
+
+        self.poster.post_message(&body).await?;
+
+        Ok(())
+    }
+
+    /// Same as [`SlackService::send_error_reply`], only visible to the user who ran the command.
+    pub async fn send_slash_command_error(
+        &self,
+        response_url: &str,
+        error: &AnswerError,
+        correlation_id: &str,
+    ) -> Result<()> {
+        let body = json!({
+            "response_type": "ephemeral",
+            "replace_original": false,
+            "blocks": error_blocks(None, error, correlation_id)
+        });
+
+        self.poster.respond(response_url, &body).await
+    }
+
+    /// Reply to an interaction or a slash command with a message only the user can see.
+    pub async fn send_ephemeral_response(&self, response_url: &str, text: &str) -> Result<()> {
+        let body = json!({
+            "response_type": "ephemeral",
+            "replace_original": false,
+            "text": text
+        });
+
+        self.poster.respond(response_url, &body).await
+    }
+
+    /// Ask the user what was wrong with the answer.
+    /// `trigger_id` is only valid for 3 seconds after the button is clicked.
+    pub async fn open_feedback_modal(&self, trigger_id: &str, answer_id: &str) -> Result<()> {
+        let body = json!({
+            "trigger_id": trigger_id,
+            "view": interaction::feedback_modal(answer_id)
+        });
+
+        self.poster.open_view(&body).await
+    }
+
+    /// Post a placeholder message to be filled in with [`StreamingMessage::update`].
+    pub async fn start_streaming_message(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<StreamingMessage> {
+        let message_ts = self
+            .post_blocks(
+                channel_id,
+                thread_ts,
+                text_blocks(&with_mention(user_id, STREAMING_PLACEHOLDER_TEXT)),
+            )
+            .await?;
+
+        Ok(StreamingMessage {
+            slack: self.clone(),
+            channel_id: channel_id.to_owned(),
+            thread_ts: thread_ts.map(|t| t.to_owned()),
+            message_ts,
+            user_id: user_id.map(|u| u.to_owned()),
+            last_update: None,
+        })
+    }
+
+    async fn post_blocks(
+        &self,
+        channel_id: &str,
+        thread_ts: Option<&str>,
+        blocks: Value,
+    ) -> Result<String> {
+        let mut body = json!({
+            "channel": channel_id,
+            "blocks": blocks
+        });
+        if let Some(thread_ts) = thread_ts {
+            body["thread_ts"] = json!(thread_ts);
+        }
+
+        self.poster.post_message(&body).await
+    }
+
+    async fn update_message(
+        &self,
+        channel_id: &str,
+        message_ts: &str,
+        blocks: Value,
+        retry: bool,
+    ) -> Result<()> {
+        let body = json!({
+            "channel": channel_id,
+            "ts": message_ts,
+            "blocks": blocks
+        });
+
+        self.poster.update_message(&body, retry).await
+    }
+}
+
+/// A message in a thread that is updated as the answer is generated.
+#[derive(Debug, Clone)]
+pub struct StreamingMessage {
+    slack: SlackService,
+    channel_id: String,
+    thread_ts: Option<String>,
+    message_ts: String,
+    user_id: Option<String>,
+    last_update: Option<Instant>,
+}
+
+impl StreamingMessage {
+    /// Replace the message with the text generated so far.
+    /// Calls within [`STREAMING_UPDATE_INTERVAL`] of the last update are skipped,
+    /// and a rate limited update is not sent again, as the next one replaces it.
+    pub async fn update(&mut self, text: &str) -> Result<()> {
+        if self
+            .last_update
+            .is_some_and(|last| last.elapsed() < STREAMING_UPDATE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_update = Some(Instant::now());
+
+        // the rest of a long answer shows up with the final result.
+        let mut blocks = text_blocks(&with_mention(
+            self.user_id.as_deref(),
+            &markdown_to_mrkdwn(text),
+        ));
+        if let Some(blocks) = blocks.as_array_mut() {
+            blocks.truncate(MAX_SECTIONS_PER_MESSAGE);
+        }
+        self.slack
+            .update_message(&self.channel_id, &self.message_ts, blocks, false)
+            .await
+    }
+
+    /// Delete the message, ex: when the answer failed and the question will be retried.
+    pub async fn discard(self) -> Result<()> {
+        let body = json!({
+            "channel": self.channel_id,
+            "ts": self.message_ts
+        });
+
+        self.slack.poster.delete_message(&body).await
+    }
+
+    /// Replace the message with the final result, citations and feedback buttons included.
+    /// A long answer is continued in follow-up messages.
+    pub async fn finish(self, result: &RetrievalResult, answer_id: Option<&str>) -> Result<()> {
+        let mut messages =
+            retrieve_result_messages(self.user_id.as_deref(), result, answer_id).into_iter();
+        if let Some(blocks) = messages.next() {
+            self.slack
+                .update_message(&self.channel_id, &self.message_ts, blocks, true)
+                .await?;
+        }
+        for blocks in messages {
+            self.slack
+                .post_blocks(&self.channel_id, self.thread_ts.as_deref(), blocks)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+// the blocks of each message, citations and feedback buttons in the last one.
+fn retrieve_result_messages(
+    user_id: Option<&str>,
+    result: &RetrievalResult,
+    answer_id: Option<&str>,
+) -> Vec<Value> {
+    let text = markdown_to_mrkdwn(&result.text_with_markers());
+    let sections = section_blocks(&with_mention(user_id, &text));
+    let mut messages: Vec<Vec<Value>> = sections
+        .chunks(MAX_SECTIONS_PER_MESSAGE)
+        .map(|blocks| blocks.to_vec())
+        .collect();
+    if messages.is_empty() {
+        messages.push(vec![]);
+    }
+
+    if let Some(last) = messages.last_mut() {
+        last.extend(citation_blocks(&result.citations));
+        if let Some(answer_id) = answer_id {
+            last.push(interaction::feedback_block(answer_id));
+        }
+    }
+
+    messages.into_iter().map(Value::Array).collect()
+}
+
+// `1. <url|Title> (Space)` and a snippet under it, for each source.
+// sources without a link, ex: a custom document, only show their title.
+fn citation_blocks(citations: &[Citation]) -> Vec<Value> {
+    if citations.is_empty() {
+        return vec![];
+    }
+    let elements: Vec<Value> = std::iter::once("Sources:".to_owned())
+        .chain(citations.iter().enumerate().map(|(index, citation)| {
+            let mut text = citation_label(index + 1, citation);
+            if let Some(snippet) = &citation.snippet {
+                text.push_str(&format!("\n{}", mrkdwn::escape(snippet)));
+            }
+            text
+        }))
+        .map(|text| json!({ "type": "mrkdwn", "text": text }))
+        .collect();
+
+    elements
+        .chunks(CONTEXT_ELEMENTS_LIMIT)
+        .map(|elements| {
+            json!({
+                "type": "context",
+                "elements": elements
+            })
+        })
+        .collect()
+}
+
+// `1. <url|Title> (Space)`, or `1. Title (Space)` without a link.
+fn citation_label(number: usize, citation: &Citation) -> String {
+    let label = mrkdwn::escape(citation.label());
+    let mut text = match &citation.url {
+        Some(url) => format!(
+            "{}. <{}|{}>",
+            number,
+            mrkdwn::escape(url),
+            label.replace('|', "¦")
+        ),
+        None => format!("{}. {}", number, label),
+    };
+    if let Some(space) = &citation.space {
+        text.push_str(&format!(" ({})", mrkdwn::escape(space)));
+    }
+
+    text
+}
+
+// a section per source, with its score and snippet.
+fn search_result_blocks(user_id: Option<&str>, query: &str, matches: &[SearchMatch]) -> Value {
+    let header = format!("Pages matching _{}_:", mrkdwn::escape(query));
+    let mut blocks = section_blocks(&with_mention(user_id, &header));
+    blocks.extend(matches.iter().enumerate().map(|(index, search_match)| {
+        let mut text = citation_label(index + 1, &search_match.citation);
+        if let Some(score) = search_match.score {
+            text.push_str(&format!(" · score {:.2}", score));
+        }
+        if let Some(snippet) = &search_match.citation.snippet {
+            text.push_str(&format!("\n>{}", mrkdwn::escape(snippet)));
+        }
+        json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": text
+            }
+        })
+    }));
+
+    Value::Array(blocks)
+}
+
+fn error_blocks(user_id: Option<&str>, error: &AnswerError, correlation_id: &str) -> Value {
+    let mut blocks = text_blocks(&with_mention(user_id, error.user_message()));
+    if let Some(blocks) = blocks.as_array_mut() {
+        blocks.push(json!({
+            "type": "context",
+            "elements": [
+                {
+                    "type": "mrkdwn",
+                    "text": format!("Reference ID: `{}`", correlation_id)
+                }
+            ]
+        }));
+    }
+
+    blocks
+}
+
+fn with_mention(user_id: Option<&str>, text: &str) -> String {
+    match user_id {
+        Some(user_id) => format!("<@{}>\n{}", user_id, text),
+        None => text.to_owned(),
+    }
+}
+
+fn text_blocks(text: &str) -> Value {
+    Value::Array(section_blocks(text))
+}
+
+// long texts are split over several sections.
+fn section_blocks(text: &str) -> Vec<Value> {
+    split_text(text, SECTION_TEXT_LIMIT)
+        .into_iter()
+        .map(|text| {
+            json!({
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": text
+                }
+            })
+        })
+        .collect()
+}
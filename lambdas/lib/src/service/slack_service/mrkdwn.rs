@@ -0,0 +1,575 @@
+//! Markdown, as answered by the models, to Slack `mrkdwn`.
+//! https://api.slack.com/reference/surfaces/formatting
+
+const CODE_FENCE: &str = "```";
+const BULLET: &str = "•";
+const HORIZONTAL_RULE: &str = "──────────";
+
+/// Convert Markdown to `mrkdwn`.
+///
+/// - `**bold**` and `__bold__` to `*bold*`, `*italic*` to `_italic_`, `~~strike~~` to `~strike~`.
+/// - `[text](url)` and `![alt](url)` to `<url|text>`.
+/// - `# headings` to bold lines, and `-`, `*`, `+` list items to `•`.
+/// - Code blocks are kept, without the language, and tables become aligned preformatted text.
+///
+/// `&`, `<` and `>` are escaped everywhere, link URLs and texts included, except for the markup
+/// of the links and the `>` starting a quote, which Slack needs as is.
+pub fn markdown_to_mrkdwn(markdown: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut output: Vec<String> = vec![];
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with(CODE_FENCE) && trimmed.matches(CODE_FENCE).count() == 1 {
+            // the language is not supported, and would show up as the first line of code.
+            output.push(CODE_FENCE.to_owned());
+            index += 1;
+            while index < lines.len() && !lines[index].trim_start().starts_with(CODE_FENCE) {
+                output.push(escape(lines[index]));
+                index += 1;
+            }
+            output.push(CODE_FENCE.to_owned());
+            index += 1;
+            continue;
+        }
+
+        if is_table_row(line) && lines.get(index + 1).is_some_and(|l| is_table_separator(l)) {
+            let mut rows = vec![table_cells(line)];
+            index += 2;
+            while index < lines.len() && is_table_row(lines[index]) {
+                rows.push(table_cells(lines[index]));
+                index += 1;
+            }
+            output.push(CODE_FENCE.to_owned());
+            output.extend(format_table(&rows));
+            output.push(CODE_FENCE.to_owned());
+            continue;
+        }
+
+        output.push(convert_line(line));
+        index += 1;
+    }
+
+    output.join("\n")
+}
+
+fn convert_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    if let Some(heading) = heading_text(trimmed) {
+        // mrkdwn has no headings, and bold cannot be nested.
+        let heading = heading.replace("**", "").replace("__", "");
+        return format!("*{}*", convert_inline(&heading, InlineMode::Plain));
+    }
+
+    if is_horizontal_rule(trimmed) {
+        return HORIZONTAL_RULE.to_owned();
+    }
+
+    if let Some(quote) = trimmed.strip_prefix('>') {
+        let quote = quote.trim_start_matches('>').trim_start();
+        return format!("> {}", convert_inline(quote, InlineMode::Mrkdwn));
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            let (checkbox, item) = if let Some(item) = item.strip_prefix("[ ] ") {
+                ("☐ ", item)
+            } else if let Some(item) = item
+                .strip_prefix("[x] ")
+                .or_else(|| item.strip_prefix("[X] "))
+            {
+                ("☑ ", item)
+            } else {
+                ("", item)
+            };
+            return format!(
+                "{}{} {}{}",
+                indent,
+                BULLET,
+                checkbox,
+                convert_inline(item, InlineMode::Mrkdwn)
+            );
+        }
+    }
+
+    format!("{}{}", indent, convert_inline(trimmed, InlineMode::Mrkdwn))
+}
+
+// `# Title` to `Title`, with up to 6 `#`.
+fn heading_text(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some(rest.trim().trim_end_matches('#').trim_end())
+}
+
+// `---`, `***` or `___`, spaces allowed in between.
+fn is_horizontal_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|c| c == *mark))
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+// `|---|:---:|`
+fn is_table_separator(line: &str) -> bool {
+    let cells = table_cells(line);
+    !cells.is_empty()
+        && cells.iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|')
+        .map(|cell| convert_inline(cell.trim(), InlineMode::Plain))
+        .collect()
+}
+
+// columns padded to the same width, with a line under the header.
+fn format_table(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| display_width(cell))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let format_row = |row: &Vec<String>| {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = row.get(column).map(|c| c.as_str()).unwrap_or("");
+                format!("{}{}", cell, " ".repeat(width - display_width(cell)))
+            })
+            .collect::<Vec<String>>()
+            .join(" | ")
+            .trim_end()
+            .to_owned()
+    };
+
+    let mut lines = vec![];
+    for (index, row) in rows.iter().enumerate() {
+        lines.push(format_row(row));
+        if index == 0 {
+            lines.push(
+                widths
+                    .iter()
+                    .map(|width| "-".repeat(*width))
+                    .collect::<Vec<String>>()
+                    .join("-+-"),
+            );
+        }
+    }
+
+    lines
+}
+
+// the escaped entities take one character on screen.
+fn display_width(text: &str) -> usize {
+    text.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .chars()
+        .count()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InlineMode {
+    Mrkdwn,
+    // for preformatted text and headings: no emphasis, links as `text (url)`.
+    Plain,
+}
+
+fn convert_inline(text: &str, mode: InlineMode) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let rest = &chars[index..];
+
+        // `code`, kept as is but escaped.
+        if c == '`' {
+            let ticks = rest.iter().take_while(|c| **c == '`').count();
+            if let Some(end) = find_run(&chars, index + ticks, '`', ticks) {
+                let code: String = chars[index + ticks..end].iter().collect();
+                match mode {
+                    InlineMode::Mrkdwn => output.push_str(&format!("`{}`", escape(code.trim()))),
+                    InlineMode::Plain => output.push_str(&escape(code.trim())),
+                }
+                index = end + ticks;
+                continue;
+            }
+        }
+
+        // [text](url) and ![alt](url)
+        let link_start = match rest {
+            ['!', '[', ..] => Some(index + 1),
+            ['[', ..] => Some(index),
+            _ => None,
+        };
+        if let Some((link_text, url, end)) = link_start.and_then(|start| parse_link(&chars, start))
+        {
+            output.push_str(&format_link(&link_text, &url, mode));
+            index = end;
+            continue;
+        }
+
+        // <https://...>, already in the Slack format.
+        if c == '<' {
+            if let Some(end) = chars[index..].iter().position(|c| *c == '>') {
+                let url: String = chars[index + 1..index + end].iter().collect();
+                if is_url(&url) && !url.contains(char::is_whitespace) {
+                    output.push_str(&format_link("", &url, mode));
+                    index += end + 1;
+                    continue;
+                }
+            }
+        }
+
+        // **bold**, __bold__ and ~~strike~~
+        if let ['*', '*', ..] | ['_', '_', ..] | ['~', '~', ..] = rest {
+            if let Some(end) = find_run(&chars, index + 2, c, 2) {
+                let inner: String = chars[index + 2..end].iter().collect();
+                if !inner.trim().is_empty() {
+                    let inner = convert_inline(&inner, mode);
+                    match (mode, c) {
+                        (InlineMode::Plain, _) => output.push_str(&inner),
+                        (InlineMode::Mrkdwn, '~') => output.push_str(&format!("~{}~", inner)),
+                        (InlineMode::Mrkdwn, _) => output.push_str(&format!("*{}*", inner)),
+                    }
+                    index = end + 2;
+                    continue;
+                }
+            }
+        }
+
+        // *italic* and _italic_, but not snake_case or `2 * 3`.
+        if (c == '*' || c == '_') && opens_emphasis(&chars, index) {
+            if let Some(end) = find_emphasis_end(&chars, index + 1, c) {
+                let inner: String = chars[index + 1..end].iter().collect();
+                let inner = convert_inline(&inner, mode);
+                match mode {
+                    InlineMode::Mrkdwn => output.push_str(&format!("_{}_", inner)),
+                    InlineMode::Plain => output.push_str(&inner),
+                }
+                index = end + 1;
+                continue;
+            }
+        }
+
+        output.push_str(&escape(&c.to_string()));
+        index += 1;
+    }
+
+    output
+}
+
+// `(text, url, index after the closing parenthesis)` of `[text](url "title")` starting at `start`.
+fn parse_link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let mut depth = 0;
+    let mut text_end = None;
+    for (index, c) in chars.iter().enumerate().skip(start) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    text_end = Some(index);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let text_end = text_end?;
+    if chars.get(text_end + 1) != Some(&'(') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut url_end = None;
+    for (index, c) in chars.iter().enumerate().skip(text_end + 1) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    url_end = Some(index);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let url_end = url_end?;
+
+    let text: String = chars[start + 1..text_end].iter().collect();
+    let target: String = chars[text_end + 2..url_end].iter().collect();
+    // drop the optional title.
+    let url = target.split_whitespace().next()?.to_owned();
+    let url = url
+        .strip_prefix('<')
+        .and_then(|u| u.strip_suffix('>'))
+        .map(|u| u.to_owned())
+        .unwrap_or(url);
+
+    Some((text, url, url_end + 1))
+}
+
+fn format_link(text: &str, url: &str, mode: InlineMode) -> String {
+    let text = convert_inline(text, InlineMode::Plain);
+    // `|` and `>` would end the link early.
+    let url = escape(url).replace('|', "%7C");
+    match mode {
+        InlineMode::Mrkdwn if text.is_empty() => format!("<{}>", url),
+        InlineMode::Mrkdwn => format!("<{}|{}>", url, text.replace('|', "¦")),
+        InlineMode::Plain if text.is_empty() || text == url => url,
+        InlineMode::Plain => format!("{} ({})", text, url),
+    }
+}
+
+fn is_url(text: &str) -> bool {
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| text.starts_with(scheme))
+}
+
+// index of the next run of exactly `count` `mark`s, from `from`.
+fn find_run(chars: &[char], from: usize, mark: char, count: usize) -> Option<usize> {
+    let mut index = from;
+    while index < chars.len() {
+        if chars[index] != mark {
+            index += 1;
+            continue;
+        }
+        let run = chars[index..].iter().take_while(|c| **c == mark).count();
+        if run == count {
+            return Some(index);
+        }
+        index += run;
+    }
+    None
+}
+
+fn opens_emphasis(chars: &[char], index: usize) -> bool {
+    let after = chars.get(index + 1);
+    let before = index.checked_sub(1).and_then(|i| chars.get(i));
+    after.is_some_and(|c| !c.is_whitespace()) && !before.is_some_and(|c| c.is_alphanumeric())
+}
+
+fn find_emphasis_end(chars: &[char], from: usize, mark: char) -> Option<usize> {
+    (from..chars.len()).find(|&index| {
+        chars[index] == mark
+            && index > from
+            && !chars[index - 1].is_whitespace()
+            && !chars
+                .get(index + 1)
+                .is_some_and(|c| c.is_alphanumeric() || *c == mark)
+    })
+}
+
+/// Escape the characters Slack uses for control sequences.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bold() {
+        assert_eq!(markdown_to_mrkdwn("a **bold** word"), "a *bold* word");
+        assert_eq!(markdown_to_mrkdwn("a __bold__ word"), "a *bold* word");
+        assert_eq!(
+            markdown_to_mrkdwn("**bold with _italic_**"),
+            "*bold with _italic_*"
+        );
+    }
+
+    #[test]
+    fn italic() {
+        assert_eq!(markdown_to_mrkdwn("an *italic* word"), "an _italic_ word");
+        assert_eq!(markdown_to_mrkdwn("an _italic_ word"), "an _italic_ word");
+    }
+
+    #[test]
+    fn snake_case_and_products_are_not_italic() {
+        assert_eq!(
+            markdown_to_mrkdwn("set max_receive_count and other_value"),
+            "set max_receive_count and other_value"
+        );
+        assert_eq!(markdown_to_mrkdwn("2 * 3 * 4 = 24"), "2 * 3 * 4 = 24");
+    }
+
+    #[test]
+    fn strikethrough() {
+        assert_eq!(markdown_to_mrkdwn("~~gone~~ here"), "~gone~ here");
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(
+            markdown_to_mrkdwn("see [the docs](https://example.com/docs)"),
+            "see <https://example.com/docs|the docs>"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("[docs](https://example.com/docs \"Title\")"),
+            "<https://example.com/docs|docs>"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("![diagram](https://example.com/a.png)"),
+            "<https://example.com/a.png|diagram>"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("[**bold** link](https://example.com)"),
+            "<https://example.com|bold link>"
+        );
+    }
+
+    #[test]
+    fn angle_bracket_urls() {
+        assert_eq!(
+            markdown_to_mrkdwn("go to <https://example.com/a>"),
+            "go to <https://example.com/a>"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("mail <mailto:team@example.com>"),
+            "mail <mailto:team@example.com>"
+        );
+        assert_eq!(markdown_to_mrkdwn("a <b> c"), "a &lt;b&gt; c");
+    }
+
+    #[test]
+    fn headings() {
+        assert_eq!(markdown_to_mrkdwn("# Title"), "*Title*");
+        assert_eq!(
+            markdown_to_mrkdwn("### **Deploy** steps ###"),
+            "*Deploy steps*"
+        );
+        assert_eq!(markdown_to_mrkdwn("#hashtag"), "#hashtag");
+        assert_eq!(markdown_to_mrkdwn("####### seven"), "####### seven");
+    }
+
+    #[test]
+    fn lists_and_checkboxes() {
+        let markdown = "- one\n* two\n+ three\n  - nested **bold**\n1. numbered";
+        assert_eq!(
+            markdown_to_mrkdwn(markdown),
+            "• one\n• two\n• three\n  • nested *bold*\n1. numbered"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("- [ ] todo\n- [x] done\n- [X] done too"),
+            "• ☐ todo\n• ☑ done\n• ☑ done too"
+        );
+    }
+
+    #[test]
+    fn code_blocks_lose_their_language() {
+        let markdown = "Run:\n```bash\necho **not bold** <here>\n```\nDone.";
+        assert_eq!(
+            markdown_to_mrkdwn(markdown),
+            "Run:\n```\necho **not bold** &lt;here&gt;\n```\nDone."
+        );
+        assert_eq!(markdown_to_mrkdwn("```\nunclosed"), "```\nunclosed\n```");
+    }
+
+    #[test]
+    fn inline_code() {
+        assert_eq!(
+            markdown_to_mrkdwn("use `a *b* c` here"),
+            "use `a *b* c` here"
+        );
+        assert_eq!(markdown_to_mrkdwn("``a ` b``"), "`a ` b`");
+    }
+
+    #[test]
+    fn tables_become_preformatted() {
+        let markdown = "| Name | Size |\n|:-----|-----:|\n| **a** | 1 |\n| bb | 22 |";
+        assert_eq!(
+            markdown_to_mrkdwn(markdown),
+            "```\nName | Size\n-----+-----\na    | 1\nbb   | 22\n```"
+        );
+    }
+
+    #[test]
+    fn horizontal_rules() {
+        for rule in ["---", "***", "___", "- - -"] {
+            assert_eq!(markdown_to_mrkdwn(rule), HORIZONTAL_RULE, "{}", rule);
+        }
+        assert_eq!(markdown_to_mrkdwn("--"), "--");
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(markdown_to_mrkdwn("> a **quote**"), "> a *quote*");
+        assert_eq!(markdown_to_mrkdwn(">> nested"), "> nested");
+    }
+
+    #[test]
+    fn plain_text_is_escaped() {
+        assert_eq!(
+            markdown_to_mrkdwn("a < b && c > d"),
+            "a &lt; b &amp;&amp; c &gt; d"
+        );
+    }
+
+    #[test]
+    fn code_is_escaped() {
+        assert_eq!(markdown_to_mrkdwn("`a<b>&c`"), "`a&lt;b&gt;&amp;c`");
+        assert_eq!(
+            markdown_to_mrkdwn("```\nif a < b && b > c {}\n```"),
+            "```\nif a &lt; b &amp;&amp; b &gt; c {}\n```"
+        );
+    }
+
+    #[test]
+    fn table_cells_are_escaped_and_aligned() {
+        let markdown = "| A | B |\n|---|---|\n| <x> | & |";
+        assert_eq!(
+            markdown_to_mrkdwn(markdown),
+            "```\nA   | B\n----+--\n&lt;x&gt; | &amp;\n```"
+        );
+    }
+
+    #[test]
+    fn link_urls_and_text_are_escaped() {
+        assert_eq!(
+            markdown_to_mrkdwn("[a & b](https://example.com/?a=1&b=2)"),
+            "<https://example.com/?a=1&amp;b=2|a &amp; b>"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("[pipe](https://example.com/a|b)"),
+            "<https://example.com/a%7Cb|pipe>"
+        );
+    }
+}
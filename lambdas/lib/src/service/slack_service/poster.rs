@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER},
+    Client, StatusCode,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{SlackApiError, SlackHttpError};
+use crate::{config::Config, env_keys::BOT_OAUTH_TOKEN, secrets::Secret};
+
+pub const POST_MESSAGE_METHOD: &str = "chat.postMessage";
+pub const UPDATE_MESSAGE_METHOD: &str = "chat.update";
+pub const DELETE_MESSAGE_METHOD: &str = "chat.delete";
+pub const OPEN_VIEW_METHOD: &str = "views.open";
+pub const USER_GROUP_USERS_METHOD: &str = "usergroups.users.list";
+pub const RESPONSE_URL_METHOD: &str = "response_url";
+
+// waiting any longer risks the lambda timing out, the message is retried from the queue instead.
+const MAX_RATE_LIMIT_RETRIES: u32 = 2;
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+const JSON_CONTENT_TYPE: &str = "application/json;charset=UTF-8";
+
+/// Sends messages to Slack. The bodies are the JSON of the corresponding Slack Web API methods.
+#[async_trait]
+pub trait ChatPoster: Debug + Send + Sync {
+    /// Fetch what posting needs, ex: the bot token, so that a missing one fails at cold start.
+    async fn load_secrets(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// `chat.postMessage`, returning the `ts` of the new message.
+    async fn post_message(&self, body: &Value) -> Result<String>;
+
+    /// `chat.update`. When rate limited, the update is sent again after `Retry-After` only if `retry`,
+    /// ex: not for the updates of a streaming message that the next one replaces.
+    async fn update_message(&self, body: &Value, retry: bool) -> Result<()>;
+
+    /// `chat.delete`
+    async fn delete_message(&self, body: &Value) -> Result<()>;
+
+    /// `views.open`
+    async fn open_view(&self, body: &Value) -> Result<()>;
+
+    /// Reply to a slash command or an interaction through its `response_url`.
+    async fn respond(&self, response_url: &str, body: &Value) -> Result<()>;
+
+    /// `usergroups.users.list`, the IDs of the users in the user group.
+    async fn user_group_members(&self, user_group_id: &str) -> Result<Vec<String>>;
+}
+
+// the methods reading data, ex: `usergroups.users.list`, only take form bodies.
+#[derive(Debug, Clone)]
+enum RequestBody {
+    Json(String),
+    Form(Vec<(String, String)>),
+}
+
+/// Slack Web API client, authenticated with the bot token.
+#[derive(Debug, Clone)]
+pub struct SlackApiClient {
+    client: Client,
+    base_url: String,
+    bot_token: Secret,
+}
+
+impl SlackApiClient {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: config.slack_api_base_url().to_owned(),
+            bot_token: Secret::new(
+                BOT_OAUTH_TOKEN,
+                config.bot_oauth_token_source(),
+                config.secrets_cache_ttl(),
+            ),
+        }
+    }
+
+    async fn post(&self, method: &str, body: &Value) -> Result<Value> {
+        self.call(
+            method,
+            &RequestBody::Json(serde_json::to_string(body)?),
+            MAX_RATE_LIMIT_RETRIES,
+        )
+        .await
+    }
+
+    async fn call(&self, method: &str, body: &RequestBody, max_retries: u32) -> Result<Value> {
+        let bot_token = self.bot_token.get().await?;
+        let body_string = self
+            .send(
+                &format!("{}/{}", self.base_url, method),
+                Some(&bot_token),
+                body,
+                max_retries,
+            )
+            .await
+            .with_context(|| format!("error calling {}", method))?;
+
+        let response: Value = serde_json::from_str(&body_string)?;
+        if response.get("ok").and_then(|ok| ok.as_bool()) != Some(true) {
+            let code = response
+                .get("error")
+                .and_then(|error| error.as_str())
+                .unwrap_or("unknown_error");
+            return Err(SlackApiError::from_code(code))
+                .with_context(|| format!("error calling {}", method));
+        }
+
+        Ok(response)
+    }
+
+    // a rate limited request is sent again after `Retry-After`, if that is soon enough,
+    // up to `max_retries` times.
+    async fn send(
+        &self,
+        url: &str,
+        bot_token: Option<&str>,
+        body: &RequestBody,
+        max_retries: u32,
+    ) -> Result<String> {
+        let mut retries = 0;
+
+        loop {
+            let mut request = match body {
+                RequestBody::Json(body) => self
+                    .client
+                    .post(url)
+                    .header(CONTENT_TYPE, JSON_CONTENT_TYPE)
+                    .body(body.to_owned()),
+                RequestBody::Form(params) => self.client.post(url).form(params),
+            };
+            if let Some(bot_token) = bot_token {
+                request = request.bearer_auth(bot_token);
+            }
+
+            let response = request.send().await?;
+            let status = response.status();
+            let retry_after = retry_after(response.headers());
+            let body_string = response.text().await?;
+            println!("response_body: {}", body_string);
+
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                check_status(status, &body_string)?;
+                return Ok(body_string);
+            }
+
+            let wait = retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+            if retries >= max_retries || wait > MAX_RETRY_AFTER {
+                return Err(SlackApiError::RateLimited { retry_after }.into());
+            }
+            retries += 1;
+            println!("rate limited by slack, retrying in {}s.", wait.as_secs());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[async_trait]
+impl ChatPoster for SlackApiClient {
+    async fn load_secrets(&self) -> Result<()> {
+        if self.bot_token.is_configured() {
+            self.bot_token.get().await?;
+        }
+        Ok(())
+    }
+
+    async fn post_message(&self, body: &Value) -> Result<String> {
+        let response = self.post(POST_MESSAGE_METHOD, body).await?;
+        let message_ts = response
+            .get("ts")
+            .and_then(|ts| ts.as_str())
+            .context("no ts for the posted message.")?;
+
+        Ok(message_ts.to_owned())
+    }
+
+    async fn update_message(&self, body: &Value, retry: bool) -> Result<()> {
+        let max_retries = if retry { MAX_RATE_LIMIT_RETRIES } else { 0 };
+        self.call(
+            UPDATE_MESSAGE_METHOD,
+            &RequestBody::Json(serde_json::to_string(body)?),
+            max_retries,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, body: &Value) -> Result<()> {
+        self.post(DELETE_MESSAGE_METHOD, body).await?;
+        Ok(())
+    }
+
+    async fn open_view(&self, body: &Value) -> Result<()> {
+        self.post(OPEN_VIEW_METHOD, body).await?;
+        Ok(())
+    }
+
+    // response_url does not take the bot token, and answers `ok` in plain text.
+    async fn respond(&self, response_url: &str, body: &Value) -> Result<()> {
+        self.send(
+            response_url,
+            None,
+            &RequestBody::Json(serde_json::to_string(body)?),
+            MAX_RATE_LIMIT_RETRIES,
+        )
+        .await
+        .context("error responding to response_url")?;
+        Ok(())
+    }
+
+    async fn user_group_members(&self, user_group_id: &str) -> Result<Vec<String>> {
+        let params = vec![("usergroup".to_owned(), user_group_id.to_owned())];
+        let response = self
+            .call(
+                USER_GROUP_USERS_METHOD,
+                &RequestBody::Form(params),
+                MAX_RATE_LIMIT_RETRIES,
+            )
+            .await?;
+        let users = response
+            .get("users")
+            .and_then(|users| users.as_array())
+            .context("no users for the user group.")?;
+
+        Ok(users
+            .iter()
+            .filter_map(|user| user.as_str())
+            .map(|user| user.to_owned())
+            .collect())
+    }
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+fn check_status(status: StatusCode, body: &str) -> Result<()> {
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(SlackHttpError {
+        status,
+        body: body.to_owned(),
+    }
+    .into())
+}
+
+/// A call made to an [`InMemoryChatPoster`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatCall {
+    /// Slack Web API method, or [`RESPONSE_URL_METHOD`].
+    pub method: &'static str,
+    /// `response_url` for [`RESPONSE_URL_METHOD`].
+    pub response_url: Option<String>,
+    pub body: Value,
+}
+
+/// Records the calls instead of sending them, for tests and local runs.
+/// A call fails only when told to with [`InMemoryChatPoster::fail_next`], and is recorded either way.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryChatPoster {
+    calls: Arc<Mutex<Vec<ChatCall>>>,
+    user_groups: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    failures: Arc<Mutex<Vec<(&'static str, String)>>>,
+}
+
+impl InMemoryChatPoster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The users returned for `user_group_id`, which is unknown to Slack until set.
+    pub fn set_user_group_members(&self, user_group_id: &str, user_ids: &[&str]) {
+        if let Ok(mut user_groups) = self.user_groups.lock() {
+            user_groups.insert(
+                user_group_id.to_owned(),
+                user_ids.iter().map(|id| id.to_string()).collect(),
+            );
+        }
+    }
+
+    /// The next call of `method` fails with the Slack `error`, ex: `channel_not_found`.
+    pub fn fail_next(&self, method: &'static str, error: &str) {
+        if let Ok(mut failures) = self.failures.lock() {
+            failures.push((method, error.to_owned()));
+        }
+    }
+
+    pub fn calls(&self) -> Vec<ChatCall> {
+        self.calls
+            .lock()
+            .map(|calls| calls.clone())
+            .unwrap_or_default()
+    }
+
+    fn record(
+        &self,
+        method: &'static str,
+        response_url: Option<&str>,
+        body: &Value,
+    ) -> Result<usize> {
+        let mut calls = self
+            .calls
+            .lock()
+            .map_err(|_| anyhow!("chat poster lock poisoned."))?;
+        calls.push(ChatCall {
+            method,
+            response_url: response_url.map(|url| url.to_owned()),
+            body: body.to_owned(),
+        });
+
+        let mut failures = self
+            .failures
+            .lock()
+            .map_err(|_| anyhow!("chat poster lock poisoned."))?;
+        if let Some(index) = failures.iter().position(|(m, _)| *m == method) {
+            let (_, error) = failures.remove(index);
+            return Err(SlackApiError::from_code(&error))
+                .with_context(|| format!("error calling {}", method));
+        }
+        Ok(calls.len())
+    }
+}
+
+#[async_trait]
+impl ChatPoster for InMemoryChatPoster {
+    async fn post_message(&self, body: &Value) -> Result<String> {
+        let count = self.record(POST_MESSAGE_METHOD, None, body)?;
+        // unique and increasing, like real message timestamps.
+        Ok(format!("{}.{:06}", chrono::Utc::now().timestamp(), count))
+    }
+
+    async fn update_message(&self, body: &Value, _retry: bool) -> Result<()> {
+        self.record(UPDATE_MESSAGE_METHOD, None, body)?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, body: &Value) -> Result<()> {
+        self.record(DELETE_MESSAGE_METHOD, None, body)?;
+        Ok(())
+    }
+
+    async fn open_view(&self, body: &Value) -> Result<()> {
+        self.record(OPEN_VIEW_METHOD, None, body)?;
+        Ok(())
+    }
+
+    async fn respond(&self, response_url: &str, body: &Value) -> Result<()> {
+        self.record(RESPONSE_URL_METHOD, Some(response_url), body)?;
+        Ok(())
+    }
+
+    async fn user_group_members(&self, user_group_id: &str) -> Result<Vec<String>> {
+        self.record(
+            USER_GROUP_USERS_METHOD,
+            None,
+            &json!({ "usergroup": user_group_id }),
+        )?;
+        let user_groups = self
+            .user_groups
+            .lock()
+            .map_err(|_| anyhow!("chat poster lock poisoned."))?;
+        match user_groups.get(user_group_id) {
+            Some(user_ids) => Ok(user_ids.to_owned()),
+            None => Err(SlackApiError::from_code("no_such_subteam"))
+                .with_context(|| format!("error calling {}", USER_GROUP_USERS_METHOD)),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+/// Slack rejects section texts longer than this.
+/// https://api.slack.com/reference/block-kit/blocks#section
+pub const SECTION_TEXT_LIMIT: usize = 3000;
+
+const CODE_FENCE: &str = "```";
+const PARAGRAPH_SEPARATOR: &str = "\n\n";
+
+/// Split `text` into parts of at most `limit` characters.
+///
+/// Parts break between paragraphs when possible, then between lines, sentences and words.
+/// A code block is kept whole if it fits in a part, otherwise it is split between lines,
+/// and each piece is closed and opened again so that it still renders as code.
+pub fn split_text(text: &str, limit: usize) -> Vec<String> {
+    let limit = limit.max(1);
+    let pieces = segments(text)
+        .into_iter()
+        .flat_map(|segment| {
+            if char_count(&segment.text) <= limit {
+                vec![segment.text]
+            } else if segment.code {
+                split_code(&segment.text, limit)
+            } else {
+                split_prose(&segment.text, limit)
+            }
+        })
+        .filter(|piece| !piece.trim().is_empty())
+        .collect();
+
+    pack(pieces, PARAGRAPH_SEPARATOR, limit)
+}
+
+// a paragraph, or a whole code block.
+#[derive(Debug)]
+struct Segment {
+    text: String,
+    code: bool,
+}
+
+fn segments(text: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut lines: Vec<&str> = vec![];
+    let mut in_code = false;
+
+    for line in text.lines() {
+        // inline code such as ```let a = 1;``` neither opens nor closes a block.
+        let is_fence =
+            line.trim_start().starts_with(CODE_FENCE) && line.matches(CODE_FENCE).count() % 2 == 1;
+
+        if in_code {
+            lines.push(line);
+            if is_fence {
+                push_segment(&mut segments, &mut lines, true);
+                in_code = false;
+            }
+        } else if is_fence {
+            push_segment(&mut segments, &mut lines, false);
+            lines.push(line);
+            in_code = true;
+        } else if line.trim().is_empty() {
+            push_segment(&mut segments, &mut lines, false);
+        } else {
+            lines.push(line);
+        }
+    }
+    // an unclosed block is closed when split.
+    push_segment(&mut segments, &mut lines, in_code);
+
+    segments
+}
+
+fn push_segment(segments: &mut Vec<Segment>, lines: &mut Vec<&str>, code: bool) {
+    if lines.is_empty() {
+        return;
+    }
+    segments.push(Segment {
+        text: lines.join("\n"),
+        code,
+    });
+    lines.clear();
+}
+
+fn split_code(text: &str, limit: usize) -> Vec<String> {
+    let mut lines = text.lines();
+    let open = lines.next().unwrap_or(CODE_FENCE).trim_end();
+    let mut body: Vec<&str> = lines.collect();
+    if body
+        .last()
+        .is_some_and(|line| line.trim_start().starts_with(CODE_FENCE))
+    {
+        body.pop();
+    }
+
+    // room left after `open\n` and `\n````.
+    let fences = char_count(open) + 2 + CODE_FENCE.len();
+    if limit <= fences {
+        // no room for the code between the fences, which are left out.
+        let pieces = body.iter().flat_map(|line| cut(line, limit)).collect();
+        return pack(pieces, "\n", limit);
+    }
+    let room = limit - fences;
+    let pieces = body.iter().flat_map(|line| cut(line, room)).collect();
+
+    pack(pieces, "\n", room)
+        .into_iter()
+        .map(|code| format!("{}\n{}\n{}", open, code, CODE_FENCE))
+        .collect()
+}
+
+fn split_prose(text: &str, limit: usize) -> Vec<String> {
+    // each piece keeps its trailing space or new line, so that they are joined back as is.
+    let mut pieces = vec![];
+    for sentence in text.split_inclusive('\n').flat_map(sentences) {
+        if char_count(sentence) <= limit {
+            pieces.push(sentence.to_owned());
+            continue;
+        }
+        for word in sentence.split_inclusive(' ') {
+            pieces.extend(cut(word, limit));
+        }
+    }
+
+    pack(pieces, "", limit)
+        .into_iter()
+        .map(|part| part.trim_end().to_owned())
+        .collect()
+}
+
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        let is_end = match c {
+            '。' | '！' | '？' => true,
+            '.' | '!' | '?' => chars.peek().is_some_and(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if !is_end {
+            continue;
+        }
+
+        let mut end = index + c.len_utf8();
+        if let Some(&(space_index, ' ')) = chars.peek() {
+            end = space_index + 1;
+            chars.next();
+        }
+        sentences.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+
+    sentences
+}
+
+// join as many pieces as fit in each part.
+fn pack(pieces: Vec<String>, separator: &str, limit: usize) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+
+    for piece in pieces {
+        if !current.is_empty()
+            && char_count(&current) + char_count(separator) + char_count(&piece) > limit
+        {
+            parts.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(&piece);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+// the last resort for a line or a word longer than the limit.
+fn cut(text: &str, limit: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(limit.max(1))
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+fn char_count(text: &str) -> usize {
+    text.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_within(parts: &[String], limit: usize) {
+        for part in parts {
+            assert!(
+                char_count(part) <= limit,
+                "{} characters over {}: {:?}",
+                char_count(part),
+                limit,
+                part
+            );
+        }
+    }
+
+    #[test]
+    fn short_text_is_one_part() {
+        assert_eq!(split_text("Hello, world.", 100), vec!["Hello, world."]);
+        assert!(split_text(" \n\n ", 100).is_empty());
+    }
+
+    #[test]
+    fn no_part_exceeds_the_limit() {
+        let text = [
+            "First paragraph. It has two sentences.",
+            "Second paragraph with a verylongwordthatdoesnotfitinanypartatall in it.",
+            "```\nlet a = 1;\nlet b = 2;\nlet c = a + b;\n```",
+            "Last line",
+        ]
+        .join("\n\n");
+
+        for limit in [1, 5, 10, 20, 40, 80] {
+            let parts = split_text(&text, limit);
+            assert!(!parts.is_empty());
+            assert_within(&parts, limit);
+        }
+    }
+
+    #[test]
+    fn breaks_between_paragraphs_then_sentences_then_words() {
+        let parts = split_text("One two.\n\nThree four.", 12);
+        assert_eq!(parts, vec!["One two.", "Three four."]);
+
+        let parts = split_text("One two. Three four.", 12);
+        assert_eq!(parts, vec!["One two.", "Three four."]);
+
+        let parts = split_text("one two three four", 9);
+        assert_eq!(parts, vec!["one two", "three", "four"]);
+    }
+
+    #[test]
+    fn code_block_that_fits_is_kept_whole() {
+        let text = "Intro.\n\n```rust\nlet a = 1;\n```";
+        let parts = split_text(text, 24);
+        assert_eq!(parts, vec!["Intro.", "```rust\nlet a = 1;\n```"]);
+    }
+
+    #[test]
+    fn oversized_code_block_is_fenced_again_with_its_language() {
+        let text = "```rust\nlet a = 1;\nlet b = 2;\nlet c = 3;\n```";
+        let parts = split_text(text, 33);
+
+        assert_eq!(
+            parts,
+            vec![
+                "```rust\nlet a = 1;\nlet b = 2;\n```",
+                "```rust\nlet c = 3;\n```",
+            ]
+        );
+        assert_within(&parts, 33);
+    }
+
+    #[test]
+    fn unclosed_code_block_is_closed_when_split() {
+        let text = "```\nline one\nline two\nline three";
+        let parts = split_text(text, 20);
+
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.starts_with("```\n"), "{:?}", part);
+            assert!(part.ends_with("\n```"), "{:?}", part);
+        }
+        assert_within(&parts, 20);
+    }
+
+    #[test]
+    fn inline_fences_do_not_open_a_block() {
+        let text = "```let a = 1;``` is inline.\n\nAfter it.";
+        let parts = split_text(text, 30);
+
+        // as a block, both paragraphs would be one segment.
+        assert_eq!(parts, vec!["```let a = 1;``` is inline.", "After it."]);
+    }
+
+    #[test]
+    fn multibyte_text_is_split_on_characters() {
+        let text = "日本語の文です。もう一つの文です。";
+        let parts = split_text(text, 9);
+
+        assert_eq!(parts, vec!["日本語の文です。", "もう一つの文です。"]);
+        assert_within(&parts, 9);
+        assert_eq!(parts.concat(), text);
+
+        let emoji = "🎉".repeat(10);
+        let parts = split_text(&emoji, 3);
+        assert_within(&parts, 3);
+        assert_eq!(parts.concat(), emoji);
+    }
+
+    #[test]
+    fn limit_smaller_than_the_fences_leaves_them_out() {
+        let text = "```rust\nabcdef\nghi\n```";
+        let parts = split_text(text, 6);
+
+        assert_eq!(parts, vec!["abcdef", "ghi"]);
+        assert_within(&parts, 6);
+    }
+}
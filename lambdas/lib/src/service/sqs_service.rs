@@ -1,6 +1,39 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
 
-use super::slack_service::MessageEventRequest;
+use super::slack_service::{MessageEventRequest, SlashCommandRequest};
+
+pub const DEFAULT_MAX_RECEIVE_COUNT: u32 = 3;
+
+/// Requests queued by the receive handler to be answered by the sqs handler.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum QueueMessage {
+    Event(MessageEventRequest),
+    SlashCommand(SlashCommandRequest),
+}
+
+impl QueueMessage {
+    /// `event_id` of events and `trigger_id` of slash commands.
+    /// Used both for the FIFO queue deduplication and as the idempotency key.
+    pub fn deduplication_id(&self) -> &str {
+        match self {
+            QueueMessage::Event(request) => &request.event_id,
+            QueueMessage::SlashCommand(request) => &request.trigger_id,
+        }
+    }
+}
+
+/// Hands the requests over from the receive handler to the sqs handler.
+#[async_trait]
+pub trait EventQueue: Debug + Send + Sync {
+    async fn send(&self, queue_url: &str, message: &QueueMessage) -> Result<()>;
+}
 
 #[derive(Debug, Clone)]
 pub struct SQSService {
@@ -13,8 +46,11 @@ impl SQSService {
             client: client.to_owned(),
         }
     }
+}
 
-    pub async fn send(&self, queue_url: &String, message: &MessageEventRequest) -> Result<()> {
+#[async_trait]
+impl EventQueue for SQSService {
+    async fn send(&self, queue_url: &str, message: &QueueMessage) -> Result<()> {
         println!("Sending message to queue with URL: {}", queue_url);
 
         let response = self
@@ -22,8 +58,8 @@ impl SQSService {
             .send_message()
             .queue_url(queue_url)
             .message_body(serde_json::to_string(&message)?)
-            .message_deduplication_id(&message.event_id)
-            .message_group_id(&message.event_id)
+            .message_deduplication_id(message.deduplication_id())
+            .message_group_id(message.deduplication_id())
             .send()
             .await?;
 
@@ -32,3 +68,34 @@ impl SQSService {
         Ok(())
     }
 }
+
+/// Keeps the sent messages in memory for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryEventQueue {
+    messages: Arc<Mutex<Vec<QueueMessage>>>,
+}
+
+impl InMemoryEventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The messages sent so far, oldest first.
+    pub fn messages(&self) -> Vec<QueueMessage> {
+        self.messages
+            .lock()
+            .map(|messages| messages.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl EventQueue for InMemoryEventQueue {
+    async fn send(&self, _queue_url: &str, message: &QueueMessage) -> Result<()> {
+        self.messages
+            .lock()
+            .map_err(|_| anyhow!("event queue lock poisoned."))?
+            .push(message.to_owned());
+        Ok(())
+    }
+}
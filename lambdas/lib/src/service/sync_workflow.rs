@@ -0,0 +1,183 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use aws_config::SdkConfig;
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningSettings},
+    sign::v4,
+};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use serde_json::json;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use super::bedrock_service::data_sync::SyncRequest;
+use crate::{config::Config, env_keys::SYNC_STATE_MACHINE_ARN};
+
+// https://docs.aws.amazon.com/step-functions/latest/apireference/API_StartExecution.html
+const STATES_SIGNING_NAME: &str = "states";
+const START_EXECUTION_TARGET: &str = "AWSStepFunctions.StartExecution";
+const AMZ_JSON_CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+const AMZ_TARGET_HEADER: &str = "x-amz-target";
+const MAX_EXECUTION_NAME_LENGTH: usize = 80;
+// the name was used by an execution with another input, or one that is finished.
+const EXECUTION_ALREADY_EXISTS_ERROR: &str = "ExecutionAlreadyExists";
+
+/// Runs a knowledge base sync outside of its schedule, ex: when an admin asks for one from Slack.
+#[async_trait]
+pub trait SyncWorkflow: Debug + Send + Sync {
+    /// Start the sync of `request`.
+    /// Starting it again with the same `name` does nothing, ex: when SQS redelivers the message
+    /// or a webhook comes within the debounce window of the previous one.
+    async fn start(&self, name: &str, request: &SyncRequest) -> Result<()>;
+}
+
+/// Starts executions of the sync state machine, the same one the schedule starts.
+///
+/// There is no Step Functions SDK here, so `StartExecution` is signed like the S3 links,
+/// with the lambda's own credentials. The lambda needs `states:StartExecution` on the state machine,
+/// which is expected to be in the region of the lambda.
+#[derive(Debug, Clone)]
+pub struct StepFunctionsSyncWorkflow {
+    client: Client,
+    credentials: Option<SharedCredentialsProvider>,
+    region: Option<String>,
+    state_machine_arn: Option<String>,
+}
+
+impl StepFunctionsSyncWorkflow {
+    pub fn new(sdk_config: &SdkConfig, config: &Config) -> Self {
+        Self {
+            client: Client::new(),
+            credentials: sdk_config.credentials_provider(),
+            region: sdk_config.region().map(|r| r.to_string()),
+            state_machine_arn: config.sync_state_machine_arn.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl SyncWorkflow for StepFunctionsSyncWorkflow {
+    async fn start(&self, name: &str, request: &SyncRequest) -> Result<()> {
+        let state_machine_arn = self
+            .state_machine_arn
+            .as_deref()
+            .with_context(|| format!("{} is not configured.", SYNC_STATE_MACHINE_ARN))?;
+        let region = self
+            .region
+            .as_deref()
+            .context("no region to start the sync in.")?;
+        let credentials = self
+            .credentials
+            .as_ref()
+            .context("no credentials to start the sync with.")?
+            .provide_credentials()
+            .await?;
+        let identity = credentials.into();
+
+        let url = format!("https://states.{}.amazonaws.com/", region);
+        let body = serde_json::to_string(&json!({
+            "stateMachineArn": state_machine_arn,
+            "name": execution_name(name),
+            "input": serde_json::to_string(request)?
+        }))?;
+        let headers = [
+            (CONTENT_TYPE.as_str(), AMZ_JSON_CONTENT_TYPE),
+            (AMZ_TARGET_HEADER, START_EXECUTION_TARGET),
+        ];
+
+        let params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(region)
+            .name(STATES_SIGNING_NAME)
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()?
+            .into();
+        let signable_request = SignableRequest::new(
+            "POST",
+            url.as_str(),
+            headers.into_iter(),
+            SignableBody::Bytes(body.as_bytes()),
+        )?;
+        let output = sign(signable_request, &params)?;
+
+        let mut http_request = self.client.post(&url).body(body.to_owned());
+        for (name, value) in headers.into_iter().chain(output.output().headers()) {
+            http_request = http_request.header(name, value);
+        }
+        let response = http_request
+            .send()
+            .await
+            .context("error starting the sync state machine")?;
+        let status = response.status();
+        let response_body = response.text().await?;
+        println!("StartExecution response: {}", response_body);
+        if status == StatusCode::BAD_REQUEST
+            && response_body.contains(EXECUTION_ALREADY_EXISTS_ERROR)
+        {
+            println!("sync {} already started.", name);
+            return Ok(());
+        }
+        if !status.is_success() {
+            bail!(
+                "error starting the sync state machine: {} {}",
+                status,
+                response_body
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// names are up to 80 letters, digits, `-` and `_`.
+fn execution_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(MAX_EXECUTION_NAME_LENGTH)
+        .collect()
+}
+
+/// Keeps the started syncs in memory instead of running them, for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySyncWorkflow {
+    started: Arc<Mutex<Vec<(String, SyncRequest)>>>,
+}
+
+impl InMemorySyncWorkflow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The names and requests of the syncs started so far, oldest first.
+    pub fn started(&self) -> Vec<(String, SyncRequest)> {
+        self.started
+            .lock()
+            .map(|started| started.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl SyncWorkflow for InMemorySyncWorkflow {
+    async fn start(&self, name: &str, request: &SyncRequest) -> Result<()> {
+        let mut started = self
+            .started
+            .lock()
+            .map_err(|_| anyhow!("sync workflow lock poisoned."))?;
+        if !started.iter().any(|(n, _)| n == name) {
+            started.push((name.to_owned(), request.to_owned()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! A local stand-in for the Slack Web API, to run the bot end to end without network.
+//!
+//! Point `SLACK_API_BASE_URL` to [`MockSlack::base_url`]. Every call is recorded,
+//! and answered the way Slack does, with `{"ok": true, ...}` or `{"ok": false, "error": ...}`.
+
+use axum::{
+    extract::{Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+pub const POST_MESSAGE_METHOD: &str = "chat.postMessage";
+pub const UPDATE_MESSAGE_METHOD: &str = "chat.update";
+pub const DELETE_MESSAGE_METHOD: &str = "chat.delete";
+pub const ADD_REACTION_METHOD: &str = "reactions.add";
+pub const OPEN_VIEW_METHOD: &str = "views.open";
+pub const USER_GROUP_USERS_METHOD: &str = "usergroups.users.list";
+/// Method of the calls made to [`MockSlack::response_url`].
+pub const RESPONSE_URL_METHOD: &str = "response_url";
+
+const RETRY_AFTER: HeaderName = HeaderName::from_static("retry-after");
+
+/// A request received by the mock.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecordedCall {
+    /// Web API method, ex: `chat.postMessage`, or [`RESPONSE_URL_METHOD`].
+    pub method: String,
+    pub body: Value,
+    /// The `Authorization` header, `Bearer <token>`.
+    pub authorization: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Failure {
+    Error(String),
+    RateLimited(Duration),
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    calls: Vec<RecordedCall>,
+    failures: HashMap<String, VecDeque<Failure>>,
+    message_count: u64,
+    user_groups: HashMap<String, Vec<String>>,
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+/// A mock Slack server running in the background until dropped.
+#[derive(Debug)]
+pub struct MockSlack {
+    address: SocketAddr,
+    state: SharedState,
+    server: JoinHandle<()>,
+}
+
+impl MockSlack {
+    /// Start on a free port of localhost.
+    pub async fn start() -> std::io::Result<Self> {
+        Self::bind("127.0.0.1:0").await
+    }
+
+    pub async fn bind(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        let state = SharedState::default();
+
+        let app = Router::new()
+            .route("/api/{method}", post(api_method))
+            .route("/response/{id}", post(response_url))
+            .route("/_calls", get(recorded_calls))
+            .with_state(state.clone());
+
+        let server = tokio::spawn(async move {
+            if let Err(error) = axum::serve(listener, app).await {
+                println!("mock slack stopped: {}", error);
+            }
+        });
+
+        Ok(Self {
+            address,
+            state,
+            server,
+        })
+    }
+
+    /// Use as `SLACK_API_BASE_URL`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/api", self.address)
+    }
+
+    /// A `response_url` to put in slash commands and interactions.
+    pub fn response_url(&self, id: &str) -> String {
+        format!("http://{}/response/{}", self.address, id)
+    }
+
+    /// All calls so far, oldest first.
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.state
+            .lock()
+            .map(|state| state.calls.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn calls_to(&self, method: &str) -> Vec<RecordedCall> {
+        self.calls()
+            .into_iter()
+            .filter(|call| call.method == method)
+            .collect()
+    }
+
+    /// Answer the next call to `method` with `{"ok": false, "error": error}`,
+    /// ex: `not_in_channel`, `channel_not_found` or `msg_too_long`.
+    pub fn fail_next(&self, method: &str, error: &str) {
+        self.push_failure(method, Failure::Error(error.to_owned()));
+    }
+
+    /// Answer the next call to `method` with HTTP 429 and `Retry-After`.
+    pub fn rate_limit_next(&self, method: &str, retry_after: Duration) {
+        self.push_failure(method, Failure::RateLimited(retry_after));
+    }
+
+    /// The users listed for `user_group_id`, which is `no_such_subteam` until set.
+    pub fn set_user_group_members(&self, user_group_id: &str, user_ids: &[&str]) {
+        if let Ok(mut state) = self.state.lock() {
+            state.user_groups.insert(
+                user_group_id.to_owned(),
+                user_ids.iter().map(|id| id.to_string()).collect(),
+            );
+        }
+    }
+
+    fn push_failure(&self, method: &str, failure: Failure) {
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .failures
+                .entry(method.to_owned())
+                .or_default()
+                .push_back(failure);
+        }
+    }
+}
+
+impl Drop for MockSlack {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn api_method(
+    State(state): State<SharedState>,
+    Path(method): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let body = parse_body(&body);
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned());
+
+    let Ok(mut state) = state.lock() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    println!("mock slack {}: {}", method, body);
+    state.calls.push(RecordedCall {
+        method: method.to_owned(),
+        body: body.to_owned(),
+        authorization: authorization.to_owned(),
+    });
+
+    if !authorization.is_some_and(|a| a.starts_with("Bearer ") && a.len() > "Bearer ".len()) {
+        return slack_error("not_authed");
+    }
+
+    let failure = state
+        .failures
+        .get_mut(&method)
+        .and_then(|failures| failures.pop_front());
+    match failure {
+        Some(Failure::Error(error)) => return slack_error(&error),
+        Some(Failure::RateLimited(retry_after)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.as_secs().to_string())],
+                Json(json!({ "ok": false, "error": "ratelimited" })),
+            )
+                .into_response();
+        }
+        None => {}
+    }
+
+    let channel = body.get("channel").and_then(|c| c.as_str());
+    match method.as_str() {
+        POST_MESSAGE_METHOD => {
+            let Some(channel) = channel else {
+                return slack_error("channel_not_found");
+            };
+            state.message_count += 1;
+            let ts = format!(
+                "{}.{:06}",
+                chrono::Utc::now().timestamp(),
+                state.message_count
+            );
+            Json(json!({
+                "ok": true,
+                "channel": channel,
+                "ts": ts,
+                "message": {
+                    "type": "message",
+                    "text": body.get("text").cloned().unwrap_or_default(),
+                    "blocks": body.get("blocks").cloned().unwrap_or_default(),
+                    "ts": ts
+                }
+            }))
+            .into_response()
+        }
+        UPDATE_MESSAGE_METHOD | DELETE_MESSAGE_METHOD => {
+            let (Some(channel), Some(ts)) = (channel, body.get("ts").and_then(|t| t.as_str()))
+            else {
+                return slack_error("message_not_found");
+            };
+            Json(json!({ "ok": true, "channel": channel, "ts": ts })).into_response()
+        }
+        ADD_REACTION_METHOD => Json(json!({ "ok": true })).into_response(),
+        USER_GROUP_USERS_METHOD => {
+            let users = body
+                .get("usergroup")
+                .and_then(|id| id.as_str())
+                .and_then(|id| state.user_groups.get(id));
+            match users {
+                Some(users) => Json(json!({ "ok": true, "users": users })).into_response(),
+                None => slack_error("no_such_subteam"),
+            }
+        }
+        OPEN_VIEW_METHOD => Json(json!({
+            "ok": true,
+            "view": body.get("view").cloned().unwrap_or_default()
+        }))
+        .into_response(),
+        _ => slack_error("unknown_method"),
+    }
+}
+
+// response_url does not take a token, and answers `ok` in plain text.
+async fn response_url(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    body: String,
+) -> Response {
+    let body = parse_body(&body);
+    println!("mock slack response_url {}: {}", id, body);
+    match state.lock() {
+        Ok(mut state) => {
+            state.calls.push(RecordedCall {
+                method: RESPONSE_URL_METHOD.to_owned(),
+                body,
+                authorization: None,
+            });
+            "ok".into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// for checking the calls from another process, ex: when running the binary.
+async fn recorded_calls(State(state): State<SharedState>) -> Response {
+    match state.lock() {
+        Ok(state) => Json(state.calls.clone()).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+// Slack takes both JSON and form bodies, the fields of a form become those of an object.
+fn parse_body(body: &str) -> Value {
+    if let Ok(value) = serde_json::from_str(body) {
+        return value;
+    }
+    match serde_urlencoded::from_str::<HashMap<String, String>>(body) {
+        Ok(fields) if body.contains('=') => json!(fields),
+        _ => Value::String(body.to_owned()),
+    }
+}
+
+fn slack_error(error: &str) -> Response {
+    Json(json!({ "ok": false, "error": error })).into_response()
+}
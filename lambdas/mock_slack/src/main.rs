@@ -0,0 +1,17 @@
+use mock_slack::MockSlack;
+
+const ADDRESS_ENV: &str = "MOCK_SLACK_ADDRESS";
+const DEFAULT_ADDRESS: &str = "127.0.0.1:3001";
+
+// run the mock on its own, ex: for a receive handler started with `cargo lambda watch`.
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let address = std::env::var(ADDRESS_ENV).unwrap_or(DEFAULT_ADDRESS.to_owned());
+    let mock = MockSlack::bind(&address).await?;
+
+    println!("SLACK_API_BASE_URL={}", mock.base_url());
+    println!("response_url: {}", mock.response_url("<id>"));
+
+    std::future::pending::<()>().await;
+    Ok(())
+}
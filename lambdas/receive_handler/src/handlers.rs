@@ -1,43 +1,48 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use axum::body::Bytes;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::header::CONTENT_TYPE;
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
-use lib::env_keys::QUEUE_URL;
-use lib::service::slack_service::{EventChallengeRequest, MessageEventRequest};
+use chrono::{SecondsFormat, Utc};
+use lib::service::bedrock_service::data_sync::SyncRequest;
+use lib::service::bedrock_service::sync_selection::SyncFilter;
+use lib::service::confluence_service::{
+    debounce_window, ConfluenceWebhookEvent, DEFAULT_SPACE_KEY,
+};
+use lib::service::feedback_store::Rating;
+use lib::service::slack_service::interaction::{
+    InteractionPayload, InteractionRequest, BLOCK_ACTIONS_TYPE, FEEDBACK_MODAL_CALLBACK_ID,
+    NEGATIVE_FEEDBACK_ACTION_ID, POSITIVE_FEEDBACK_ACTION_ID, VIEW_SUBMISSION_TYPE,
+};
+use lib::service::slack_service::{
+    EventChallengeRequest, MessageEventRequest, SlashCommandRequest, ASK_CONFLUENCE_COMMAND,
+    FIND_FLAG, PRIVATE_FLAG,
+};
+use lib::service::sqs_service::QueueMessage;
 use lib::service::CommonService;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 
 const REQUEST_TIMESTAMP_HEADER: &str = "X-Slack-Request-Timestamp";
 const REQUEST_SIGNATURE_HEADER: &str = "X-Slack-Signature";
+const RETRY_NUM_HEADER: &str = "X-Slack-Retry-Num";
+const RETRY_REASON_HEADER: &str = "X-Slack-Retry-Reason";
+const CONFLUENCE_SIGNATURE_HEADER: &str = "X-Hub-Signature";
+// `/confluence?event=page_updated`, for the webhooks leaving it out of the body.
+const CONFLUENCE_EVENT_QUERY_KEY: &str = "event";
+
+const UNAVAILABLE_MESSAGE: &str = "Sorry, I am not able to take questions right now.";
+const FEEDBACK_THANKS_MESSAGE: &str = "Thanks for the feedback!";
 
 pub async fn webhook_received(
     State(service): State<CommonService>,
     headers: HeaderMap,
     bytes: Bytes,
 ) -> Response {
-    let (timestamp, received_signature) = match get_timestamp_signature(&headers) {
-        Ok((t, s)) => (t, s),
-        Err(error) => {
-            println!("error getting timestamp and signature: {}", error);
-            return build_error_response(&error.to_string());
-        }
-    };
-
-    let Ok(body_string) = String::from_utf8(bytes.to_vec()) else {
-        println!("error getting body as string.");
-        return build_error_response("error getting body as string.");
-    };
-
-    let verification_result =
-        service
-            .slack
-            .verify_signature(timestamp, &body_string, &received_signature);
-
-    if verification_result.is_err() || verification_result.unwrap() == false {
-        println!("Error verifying request.");
-        return build_error_response("Error Verifying request.");
+    if let Err(error) = verify_request(&service, &headers, &bytes).await {
+        println!("Error verifying request: {}", error);
+        return build_error_response(&error.to_string());
     }
 
     let value: Value = serde_json::from_slice(&bytes).unwrap();
@@ -70,19 +75,345 @@ pub async fn webhook_received(
         return build_success_response(&json!({}));
     }
 
-    let Ok(queue_url) = std::env::var(QUEUE_URL) else {
+    if let Some((retry_num, retry_reason)) = get_retry(&headers) {
+        println!(
+            "Slack retry {} ({}) for event {}",
+            retry_num, retry_reason, message_request.event_id
+        );
+    }
+
+    let Some(queue_url) = &service.config.queue_url else {
         println!("SQS URL not availabe");
         return build_success_response(&json!({}));
     };
 
-    match service.sqs.send(&queue_url, &message_request).await {
-        Ok(_) => {}
+    let message = QueueMessage::Event(message_request);
+    if let Err(error) = enqueue_once(&service, queue_url, &message).await {
+        println!("Error sending to sqs: {}", error);
+        // let Slack retry it.
+        return build_error_response("Error queuing the event.");
+    }
+
+    build_success_response(&json!({}))
+}
+
+// Slack retries events when we are slow to respond, so queue each of them only once.
+// sqs handler checks again before answering, in case SQS redelivers it.
+async fn enqueue_once(
+    service: &CommonService,
+    queue_url: &str,
+    message: &QueueMessage,
+) -> anyhow::Result<()> {
+    let key = message.deduplication_id();
+    match service.idempotency_store.mark_received(key).await {
+        Ok(true) => {}
+        Ok(false) => {
+            println!("{} already received.", key);
+            return Ok(());
+        }
+        // the sqs handler check still protects us, so keep going.
+        Err(error) => println!("Error recording {}: {}", key, error),
+    }
+
+    if let Err(error) = service.queue.send(queue_url, message).await {
+        if let Err(error) = service.idempotency_store.forget(key).await {
+            println!("Error forgetting {}: {}", key, error);
+        }
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+// https://api.slack.com/interactivity/slash-commands
+// Slack expects a response within 3 seconds, so only acknowledge here and answer through the response_url.
+pub async fn slash_command_received(
+    State(service): State<CommonService>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Response {
+    if let Err(error) = verify_request(&service, &headers, &bytes).await {
+        println!("Error verifying request: {}", error);
+        return build_error_response(&error.to_string());
+    }
+
+    let command_request = match serde_urlencoded::from_bytes::<SlashCommandRequest>(&bytes) {
+        Ok(request) => request,
+        Err(error) => {
+            println!("Error converting to slash command request: {:?}", error);
+            return build_error_response("Error parsing slash command.");
+        }
+    };
+
+    if !service.slack.verify_slash_command_request(&command_request) {
+        println!("unknown command: {}", command_request.command);
+        return build_error_response("Unknown command.");
+    }
+
+    let options = command_request.options();
+    if options.question.is_empty() {
+        return build_success_response(&json!({
+            "response_type": "ephemeral",
+            "text": format!("Usage: `{} [{}] [{}] <question>`", ASK_CONFLUENCE_COMMAND, PRIVATE_FLAG, FIND_FLAG)
+        }));
+    }
+
+    let Some(queue_url) = &service.config.queue_url else {
+        println!("SQS URL not availabe");
+        return build_success_response(&json!({
+            "response_type": "ephemeral",
+            "text": UNAVAILABLE_MESSAGE
+        }));
+    };
+
+    if let Err(error) = enqueue_once(
+        &service,
+        queue_url,
+        &QueueMessage::SlashCommand(command_request),
+    )
+    .await
+    {
+        println!("Error sending to sqs: {}", error);
+        return build_success_response(&json!({
+            "response_type": "ephemeral",
+            "text": UNAVAILABLE_MESSAGE
+        }));
+    }
+
+    if options.private {
+        build_success_response(&json!({
+            "response_type": "ephemeral",
+            "text": format!(":hourglass_flowing_sand: Looking into Confluence for: {}", options.question)
+        }))
+    } else {
+        // echo the command to the channel so that everyone can see the question.
+        build_success_response(&json!({
+            "response_type": "in_channel"
+        }))
+    }
+}
+
+// https://api.slack.com/interactivity/handling
+// feedback buttons on the answers and the modal asking what was wrong.
+pub async fn interaction_received(
+    State(service): State<CommonService>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Response {
+    if let Err(error) = verify_request(&service, &headers, &bytes).await {
+        println!("Error verifying request: {}", error);
+        return build_error_response(&error.to_string());
+    }
+
+    let payload = match parse_interaction_payload(&bytes) {
+        Ok(payload) => payload,
         Err(error) => {
-            println!("Error sending to sqs: {}", error);
+            println!("Error converting to interaction payload: {:?}", error);
+            return build_error_response("Error parsing interaction payload.");
+        }
+    };
+
+    let result = match payload.r#type.as_str() {
+        BLOCK_ACTIONS_TYPE => process_block_actions(&service, &payload).await,
+        VIEW_SUBMISSION_TYPE => process_view_submission(&service, &payload).await,
+        _ => {
+            println!("unhandled interaction: {}", payload.r#type);
+            Ok(())
+        }
+    };
+    if let Err(error) = result {
+        println!("Error processing interaction: {}", error);
+    }
+
+    // an empty response also closes the modal on view submissions.
+    build_success_response(&json!({}))
+}
+
+fn parse_interaction_payload(bytes: &Bytes) -> anyhow::Result<InteractionPayload> {
+    let request = serde_urlencoded::from_bytes::<InteractionRequest>(bytes)?;
+    Ok(serde_json::from_str::<InteractionPayload>(
+        &request.payload,
+    )?)
+}
+
+async fn process_block_actions(
+    service: &CommonService,
+    payload: &InteractionPayload,
+) -> anyhow::Result<()> {
+    for action in payload.actions.iter() {
+        let rating = match action.action_id.as_str() {
+            POSITIVE_FEEDBACK_ACTION_ID => Rating::Positive,
+            NEGATIVE_FEEDBACK_ACTION_ID => Rating::Negative,
+            _ => continue,
+        };
+        let answer_id = action
+            .value
+            .as_deref()
+            .context("no answer id for feedback.")?;
+
+        service
+            .feedback_store
+            .put_rating(answer_id, &payload.user.id, rating)
+            .await?;
+
+        match (rating, &payload.trigger_id, &payload.response_url) {
+            (Rating::Negative, Some(trigger_id), _) => {
+                service
+                    .slack
+                    .open_feedback_modal(trigger_id, answer_id)
+                    .await?
+            }
+            (Rating::Positive, _, Some(response_url)) => {
+                service
+                    .slack
+                    .send_ephemeral_response(response_url, FEEDBACK_THANKS_MESSAGE)
+                    .await?
+            }
+            _ => {}
         }
     }
 
-    return build_success_response(&json!({}));
+    Ok(())
+}
+
+async fn process_view_submission(
+    service: &CommonService,
+    payload: &InteractionPayload,
+) -> anyhow::Result<()> {
+    let Some(view) = &payload.view else {
+        return Ok(());
+    };
+    if view.callback_id != FEEDBACK_MODAL_CALLBACK_ID {
+        return Ok(());
+    }
+    let Some(comment) = view.feedback_comment() else {
+        return Ok(());
+    };
+
+    service
+        .feedback_store
+        .put_comment(&view.private_metadata, &payload.user.id, comment)
+        .await
+}
+
+// https://developer.atlassian.com/cloud/confluence/modules/webhook/
+// a page changed, so sync its data source once the debounce window ends.
+// every event of the window names the same execution, which only starts once.
+pub async fn confluence_webhook_received(
+    State(service): State<CommonService>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Response {
+    if let Err(error) = verify_confluence_request(&service, &headers, &bytes).await {
+        println!("Error verifying confluence request: {}", error);
+        return build_error_response(&error.to_string());
+    }
+
+    let event = match serde_json::from_slice::<ConfluenceWebhookEvent>(&bytes) {
+        Ok(event) => event,
+        Err(error) => {
+            println!("Error converting to confluence event: {:?}", error);
+            return build_error_response("Error parsing confluence event.");
+        }
+    };
+    let event_name = event
+        .event
+        .as_deref()
+        .or(query.get(CONFLUENCE_EVENT_QUERY_KEY).map(|e| e.as_str()));
+
+    if let Err(error) = process_confluence_event(&service, event_name, &event).await {
+        println!("Error processing confluence event: {}", error);
+        return build_error_response("Error starting the sync.");
+    }
+
+    build_success_response(&json!({}))
+}
+
+async fn process_confluence_event(
+    service: &CommonService,
+    event_name: Option<&str>,
+    event: &ConfluenceWebhookEvent,
+) -> anyhow::Result<()> {
+    let Some(event_name) = event_name.filter(|e| ConfluenceWebhookEvent::is_page_event(e)) else {
+        println!("unhandled confluence event: {:?}", event_name);
+        return Ok(());
+    };
+    let page = event.page.as_ref().context("no page in the event.")?;
+    let space_key = page.space_key.as_deref().unwrap_or(DEFAULT_SPACE_KEY);
+    let Some(data_source) = service.config.confluence_data_source(space_key) else {
+        println!("space {} is not synced.", space_key);
+        return Ok(());
+    };
+    let data_source = service.data_sync.data_source(data_source).await?;
+
+    // a custom data source is given the page itself, the others crawl the whole space.
+    let (window, start_at) = debounce_window(Utc::now(), service.config.confluence_debounce());
+    let (name, pages) = if data_source.custom {
+        (
+            format!("confluence-{}-{}-{}", data_source.id, page.id, window),
+            vec![page.id.to_owned()],
+        )
+    } else {
+        (format!("confluence-{}-{}", data_source.id, window), vec![])
+    };
+    let request = SyncRequest {
+        filter: SyncFilter {
+            data_sources: vec![data_source.id.to_owned()],
+            ..Default::default()
+        },
+        pages,
+        start_at: Some(start_at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ..Default::default()
+    };
+
+    println!(
+        "{} of page {}, syncing {} at {}",
+        event_name, page.id, data_source.name, start_at
+    );
+    service.sync_workflow.start(&name, &request).await
+}
+
+async fn verify_confluence_request(
+    service: &CommonService,
+    headers: &HeaderMap,
+    bytes: &Bytes,
+) -> anyhow::Result<()> {
+    let received_signature = headers
+        .get(CONFLUENCE_SIGNATURE_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .context("No signature received.")?;
+
+    let verified = service
+        .confluence
+        .verify_signature(bytes, received_signature)
+        .await?;
+    if !verified {
+        bail!("Error Verifying request.");
+    }
+
+    Ok(())
+}
+
+// https://api.slack.com/authentication/verifying-requests-from-slack
+async fn verify_request(
+    service: &CommonService,
+    headers: &HeaderMap,
+    bytes: &Bytes,
+) -> anyhow::Result<()> {
+    let (timestamp, received_signature) = get_timestamp_signature(headers)?;
+
+    let body_string = String::from_utf8(bytes.to_vec()).context("error getting body as string.")?;
+
+    let verified = service
+        .slack
+        .verify_signature(timestamp, &body_string, &received_signature)
+        .await?;
+    if !verified {
+        bail!("Error Verifying request.");
+    }
+
+    Ok(())
 }
 
 fn get_timestamp_signature(headers: &HeaderMap) -> anyhow::Result<(u64, String)> {
@@ -97,7 +428,21 @@ fn get_timestamp_signature(headers: &HeaderMap) -> anyhow::Result<(u64, String)>
         .and_then(|header| header.to_str().ok())
         .context("No signature received.")?;
 
-    return Ok((timestamp, received_signature.to_owned()));
+    Ok((timestamp, received_signature.to_owned()))
+}
+
+// https://api.slack.com/apis/events-api#retries
+fn get_retry(headers: &HeaderMap) -> Option<(u32, String)> {
+    let retry_num = headers
+        .get(RETRY_NUM_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|num| num.parse().ok())?;
+    let retry_reason = headers
+        .get(RETRY_REASON_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or_default();
+
+    Some((retry_num, retry_reason.to_owned()))
 }
 
 fn build_error_response(message: &str) -> Response {
@@ -112,12 +457,162 @@ fn build_error_response(message: &str) -> Response {
         .to_string(),
     );
     *response.status_mut() = StatusCode::BAD_REQUEST;
-    return (json_header, response).into_response();
+    (json_header, response).into_response()
 }
 
 fn build_success_response(body: &Value) -> Response {
     let mut json_header = HeaderMap::new();
     json_header.insert(CONTENT_TYPE, "application/json".parse().unwrap());
     let response = Response::new(body.to_string());
-    return (json_header, response).into_response();
+    (json_header, response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use hmac::{Hmac, Mac};
+    use lib::{config::Config, service::InMemoryServices};
+    use sha2::Sha256;
+
+    const SIGNING_SECRET: &str = "signing-secret";
+    const QUEUE_URL: &str = "https://sqs.us-east-1.amazonaws.com/123456789012/SlackEventQueue.fifo";
+
+    fn config() -> Config {
+        Config {
+            slack_signing_secret: Some(SIGNING_SECRET.to_owned()),
+            queue_url: Some(QUEUE_URL.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn signed_headers(body: &str) -> HeaderMap {
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(SIGNING_SECRET.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_TIMESTAMP_HEADER, timestamp.parse().unwrap());
+        headers.insert(REQUEST_SIGNATURE_HEADER, signature.parse().unwrap());
+        headers
+    }
+
+    fn mention(event_id: &str) -> String {
+        json!({
+            "token": "token",
+            "api_app_id": "A0123ABCD",
+            "type": "event_callback",
+            "event_id": event_id,
+            "event_time": 1700000000,
+            "event": {
+                "type": "app_mention",
+                "channel": "C0123ABCD",
+                "user": "U0123ABCD",
+                "text": "<@U0BOT> how do I deploy?",
+                "ts": "1700000000.000100",
+                "event_ts": "1700000000.000100"
+            }
+        })
+        .to_string()
+    }
+
+    async fn receive(
+        service: &CommonService,
+        headers: HeaderMap,
+        body: &str,
+    ) -> (StatusCode, Value) {
+        let response = webhook_received(
+            State(service.clone()),
+            headers,
+            Bytes::from(body.to_owned()),
+        )
+        .await;
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn answers_the_url_verification_challenge() {
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let body = json!({
+            "token": "token",
+            "challenge": "3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P",
+            "type": "url_verification"
+        })
+        .to_string();
+
+        let (status, response) = receive(&service, signed_headers(&body), &body).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response["challenge"],
+            "3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P"
+        );
+        assert!(services.queue.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn queues_a_mention() {
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let body = mention("Ev1");
+
+        let (status, _) = receive(&service, signed_headers(&body), &body).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let messages = services.queue.messages();
+        assert_eq!(messages.len(), 1);
+        let QueueMessage::Event(request) = &messages[0] else {
+            panic!("expected an event, got {:?}", messages[0]);
+        };
+        assert_eq!(request.event_id, "Ev1");
+        assert_eq!(request.event.text, "<@U0BOT> how do I deploy?");
+    }
+
+    #[tokio::test]
+    async fn queues_a_retried_event_once() {
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let body = mention("Ev1");
+
+        receive(&service, signed_headers(&body), &body).await;
+        let mut headers = signed_headers(&body);
+        headers.insert(RETRY_NUM_HEADER, "1".parse().unwrap());
+        headers.insert(RETRY_REASON_HEADER, "http_timeout".parse().unwrap());
+        let (status, _) = receive(&service, headers, &body).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(services.queue.messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_signature() {
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let body = mention("Ev1");
+        let headers = signed_headers(&mention("Ev2"));
+
+        let (status, response) = receive(&service, headers, &body).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(response["success"], false);
+        assert!(services.queue.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignores_bot_messages() {
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let mut body: Value = serde_json::from_str(&mention("Ev1")).unwrap();
+        body["event"]["bot_id"] = json!("B0123ABCD");
+        let body = body.to_string();
+
+        let (status, _) = receive(&service, signed_headers(&body), &body).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(services.queue.messages().is_empty());
+    }
 }
@@ -1,9 +1,13 @@
-pub mod handlers;
 use axum::routing::post;
 use axum::Router;
-use handlers::webhook_received;
 use lambda_http::{run, tracing, Error};
-use lib::service::CommonService;
+use lib::{
+    env_keys::{QUEUE_URL, SLACK_SIGNING_SECRET},
+    service::CommonService,
+};
+use receive_handler::handlers::{
+    confluence_webhook_received, interaction_received, slash_command_received, webhook_received,
+};
 use std::env::set_var;
 
 #[tokio::main]
@@ -12,11 +16,14 @@ async fn main() -> Result<(), Error> {
 
     tracing::init_default_subscriber();
 
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::v2025_08_07()).await;
-    let service = CommonService::new(&config);
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::v2026_01_12()).await;
+    let service = CommonService::new(&config, &[SLACK_SIGNING_SECRET, QUEUE_URL]).await?;
 
     let app = Router::new()
         .route("/", post(post(webhook_received)))
+        .route("/commands", post(slash_command_received))
+        .route("/interactions", post(interaction_received))
+        .route("/confluence", post(confluence_webhook_received))
         .with_state(service);
 
     run(app).await
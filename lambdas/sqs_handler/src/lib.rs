@@ -0,0 +1,713 @@
+use anyhow::Context;
+use aws_lambda_events::sqs::{BatchItemFailure, SqsBatchResponse, SqsEvent, SqsMessage};
+use lib::{
+    env_keys::QUEUE_ARN,
+    error::AnswerError,
+    service::{
+        bedrock_service::{
+            data_sync::{SyncReply, SyncRequest},
+            settings::RetrievalSettings,
+            sync_selection::SyncFilter,
+            RetrievalResult,
+        },
+        feedback_store::AnswerRecord,
+        slack_service::{
+            admin_sync_request, search_query, MessageEvent, SlashCommandRequest, StreamingMessage,
+        },
+        sqs_service::QueueMessage,
+        CommonService,
+    },
+};
+use regex::Regex;
+use std::time::Duration;
+
+// as long as the Lambda timeout, after which a crashed attempt can be taken over.
+const PROCESSING_LEASE: Duration = Duration::from_secs(60 * 5);
+
+const APPROXIMATE_RECEIVE_COUNT_ATTRIBUTE: &str = "ApproximateReceiveCount";
+
+const ADMIN_ONLY_MESSAGE: &str = "Sorry, only admins can sync the knowledge base.";
+
+// only the messages reported in `batch_item_failures` go back to the queue,
+// the others are deleted whether they succeeded or failed for good.
+pub async fn process_event(
+    event: SqsEvent,
+    service: &CommonService,
+) -> anyhow::Result<SqsBatchResponse> {
+    let queue_arn = service
+        .config
+        .queue_arn
+        .as_deref()
+        .with_context(|| format!("{} is not configured.", QUEUE_ARN))?;
+    let streaming_enabled = service.config.streaming_enabled;
+
+    let mut batch_item_failures = vec![];
+
+    for record in event.records.into_iter() {
+        if record
+            .event_source_arn
+            .as_ref()
+            .is_some_and(|arn| arn != queue_arn)
+        {
+            println!("wrong event source ");
+            continue;
+        }
+
+        let Some(message_string) = &record.body else {
+            continue;
+        };
+
+        let message = match serde_json::from_str::<QueueMessage>(message_string) {
+            Ok(message) => message,
+            Err(error) => {
+                println!("error parsing message: {:?}", error);
+                continue;
+            }
+        };
+
+        // SQS redelivers the message if the visibility timeout passes before we delete it.
+        let key = message.deduplication_id().to_owned();
+        match service
+            .idempotency_store
+            .start_processing(&key, PROCESSING_LEASE)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("{} already answered or being answered.", key);
+                continue;
+            }
+            Err(error) => println!("error checking {}: {}", key, error),
+        }
+
+        let result = match &message {
+            QueueMessage::Event(request) => {
+                process_message_event(service, &request.event, streaming_enabled).await
+            }
+            QueueMessage::SlashCommand(request) => process_slash_command(service, request).await,
+        };
+
+        let recorded = match result {
+            Ok(_) => service.idempotency_store.mark_completed(&key).await,
+            Err(error) => {
+                let error = AnswerError::from(error);
+                // the key doubles as the correlation id given to the asker.
+                println!("[{}] error answering: {:?}", key, error);
+
+                if error.is_retryable()
+                    && receive_count(&record) < service.config.max_receive_count()
+                {
+                    if let Some(message_id) = record.message_id.clone() {
+                        batch_item_failures.push(BatchItemFailure {
+                            item_identifier: message_id,
+                        });
+                    }
+                    service.idempotency_store.release(&key).await
+                } else {
+                    // the reply would fail the same way, ex: the bot was removed from the channel.
+                    let channel_unavailable = error
+                        .slack_api_error()
+                        .is_some_and(|e| e.is_channel_unavailable());
+                    if channel_unavailable {
+                        println!("[{}] cannot post to the channel, not replying.", key);
+                    } else if let Err(error) = reply_error(service, &message, &error, &key).await {
+                        println!("[{}] error replying: {}", key, error);
+                    }
+                    service.idempotency_store.mark_completed(&key).await
+                }
+            }
+        };
+        if let Err(error) = recorded {
+            println!("error recording {}: {}", key, error);
+        }
+    }
+
+    Ok(SqsBatchResponse {
+        batch_item_failures,
+    })
+}
+
+fn receive_count(record: &SqsMessage) -> u32 {
+    record
+        .attributes
+        .get(APPROXIMATE_RECEIVE_COUNT_ATTRIBUTE)
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+}
+
+async fn reply_error(
+    service: &CommonService,
+    message: &QueueMessage,
+    error: &AnswerError,
+    correlation_id: &str,
+) -> anyhow::Result<()> {
+    match message {
+        QueueMessage::Event(request) => {
+            let event = &request.event;
+            service
+                .slack
+                .send_error_reply(
+                    &event.channel,
+                    event.reply_thread_ts(),
+                    event.reply_user(),
+                    error,
+                    correlation_id,
+                )
+                .await
+        }
+        QueueMessage::SlashCommand(request) => {
+            service
+                .slack
+                .send_slash_command_error(&request.response_url, error, correlation_id)
+                .await
+        }
+    }
+}
+
+async fn process_message_event(
+    service: &CommonService,
+    event: &MessageEvent,
+    streaming_enabled: bool,
+) -> anyhow::Result<()> {
+    let input = remove_user_id(&event.text);
+
+    if input.is_empty() {
+        return Ok(());
+    }
+    if let Some(data_sources) = admin_sync_request(&input) {
+        return admin_sync(service, event, data_sources).await;
+    }
+    let settings = service.config.retrieval_settings(&event.channel);
+    if let Some(query) = search_query(&input) {
+        return find(service, event, query, &settings).await;
+    }
+    let conversation_ts = event.conversation_ts();
+
+    // a missing session only costs the conversation history, so keep answering.
+    let session_id = match service
+        .session_store
+        .get(&event.channel, conversation_ts)
+        .await
+    {
+        Ok(s) => s,
+        Err(error) => {
+            println!("error getting session: {}", error);
+            None
+        }
+    };
+
+    let result = if streaming_enabled {
+        stream_answer(service, event, &input, session_id.as_deref(), &settings).await?
+    } else {
+        answer(service, event, &input, session_id.as_deref(), &settings).await?
+    };
+
+    if session_id.as_deref() != Some(result.session_id.as_str()) {
+        if let Err(error) = service
+            .session_store
+            .put(&event.channel, conversation_ts, &result.session_id)
+            .await
+        {
+            println!("error saving session: {}", error);
+        }
+    }
+
+    Ok(())
+}
+
+// slash commands are not tied to a thread, so every command starts a new session.
+async fn process_slash_command(
+    service: &CommonService,
+    request: &SlashCommandRequest,
+) -> anyhow::Result<()> {
+    let options = request.options();
+
+    if options.question.is_empty() {
+        return Ok(());
+    }
+
+    let settings = service.config.retrieval_settings(&request.channel_id);
+    if options.find {
+        let matches = service
+            .retriever
+            .search(&options.question, &settings)
+            .await?;
+        return service
+            .slack
+            .send_slash_command_search_result(
+                &request.response_url,
+                options.private,
+                &request.user_id,
+                &options.question,
+                &matches,
+            )
+            .await;
+    }
+
+    let result = service
+        .retriever
+        .retrieve(&options.question, None, &settings)
+        .await?;
+    let answer_id = save_answer(service, &options.question, &request.channel_id, &result).await;
+
+    service
+        .slack
+        .send_slash_command_result(
+            &request.response_url,
+            options.private,
+            &request.user_id,
+            &result,
+            answer_id.as_deref(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn answer(
+    service: &CommonService,
+    event: &MessageEvent,
+    input: &str,
+    session_id: Option<&str>,
+    settings: &RetrievalSettings,
+) -> anyhow::Result<RetrievalResult> {
+    let result = service
+        .retriever
+        .retrieve(input, session_id, settings)
+        .await?;
+    let answer_id = save_answer(service, input, &event.channel, &result).await;
+
+    service
+        .slack
+        .send_retrieve_result(
+            &event.channel,
+            event.reply_thread_ts(),
+            event.reply_user(),
+            &result,
+            answer_id.as_deref(),
+        )
+        .await?;
+
+    Ok(result)
+}
+
+// list the matching pages only, outside of the conversation of the thread.
+async fn find(
+    service: &CommonService,
+    event: &MessageEvent,
+    query: &str,
+    settings: &RetrievalSettings,
+) -> anyhow::Result<()> {
+    let matches = service.retriever.search(query, settings).await?;
+
+    service
+        .slack
+        .send_search_result(
+            &event.channel,
+            event.reply_thread_ts(),
+            event.reply_user(),
+            query,
+            &matches,
+        )
+        .await
+}
+
+// the sync state machine posts the progress in the thread, the same way it checks on the daily sync.
+async fn admin_sync(
+    service: &CommonService,
+    event: &MessageEvent,
+    data_sources: Vec<String>,
+) -> anyhow::Result<()> {
+    let config = &service.config;
+    let allowed = service
+        .slack
+        .is_allowed(
+            &event.user,
+            &config.admin_user_ids,
+            &config.admin_user_group_ids,
+        )
+        .await;
+    if !allowed {
+        println!("{} is not allowed to sync.", event.user);
+        return service
+            .slack
+            .send_text_reply(
+                &event.channel,
+                event.reply_thread_ts(),
+                event.reply_user(),
+                ADMIN_ONLY_MESSAGE,
+            )
+            .await;
+    }
+
+    println!("{} is syncing data sources: {:?}", event.user, data_sources);
+    let request = SyncRequest {
+        filter: SyncFilter {
+            data_sources,
+            ..Default::default()
+        },
+        reply: Some(SyncReply {
+            channel_id: event.channel.to_owned(),
+            thread_ts: event.reply_thread_ts().map(|ts| ts.to_owned()),
+            user_id: event.reply_user().map(|user| user.to_owned()),
+            message_ts: None,
+        }),
+        ..Default::default()
+    };
+    // the same name for the same message, so that a redelivery does not start another sync.
+    let name = format!("admin-sync-{}-{}", event.channel, event.event_ts);
+    service.sync_workflow.start(&name, &request).await
+}
+
+// post a placeholder and fill it in with chat.update while the answer is being generated.
+async fn stream_answer(
+    service: &CommonService,
+    event: &MessageEvent,
+    input: &str,
+    session_id: Option<&str>,
+    settings: &RetrievalSettings,
+) -> anyhow::Result<RetrievalResult> {
+    let mut message = service
+        .slack
+        .start_streaming_message(&event.channel, event.reply_thread_ts(), event.reply_user())
+        .await?;
+    let result = match generate(service, &mut message, input, session_id, settings).await {
+        Ok(result) => result,
+        Err(error) => {
+            // the error is replied in a new message, or the question retried with a new placeholder.
+            if let Err(error) = message.discard().await {
+                println!("error deleting streaming message: {}", error);
+            }
+            return Err(error);
+        }
+    };
+
+    let answer_id = save_answer(service, input, &event.channel, &result).await;
+    message.finish(&result, answer_id.as_deref()).await?;
+
+    Ok(result)
+}
+
+async fn generate(
+    service: &CommonService,
+    message: &mut StreamingMessage,
+    input: &str,
+    session_id: Option<&str>,
+    settings: &RetrievalSettings,
+) -> anyhow::Result<RetrievalResult> {
+    let mut stream = service
+        .retriever
+        .retrieve_stream(input, session_id, settings)
+        .await?;
+
+    while stream.next_chunk().await?.is_some() {
+        if let Err(error) = message.update(stream.text()).await {
+            println!("error updating streaming message: {}", error);
+        }
+    }
+
+    stream.into_result().await
+}
+
+// keep the answer for rating it with the feedback buttons.
+// if it cannot be saved, answer without the buttons.
+async fn save_answer(
+    service: &CommonService,
+    question: &str,
+    channel_id: &str,
+    result: &RetrievalResult,
+) -> Option<String> {
+    let record = AnswerRecord::new(question, channel_id, result, &service.config);
+    match service.feedback_store.put_answer(&record).await {
+        Ok(_) => Some(record.answer_id),
+        Err(error) => {
+            println!("error saving answer: {}", error);
+            None
+        }
+    }
+}
+
+fn remove_user_id(text: &str) -> String {
+    let Ok(re) = Regex::new(r"(<@(.*?)>)*") else {
+        return text.to_owned();
+    };
+
+    let new = re.replace_all(text, "");
+    new.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::{
+        config::Config,
+        service::{
+            slack_service::{
+                poster::{ChatCall, POST_MESSAGE_METHOD},
+                MessageEventRequest,
+            },
+            InMemoryServices,
+        },
+    };
+    use std::collections::HashMap;
+
+    const QUEUE: &str = "arn:aws:sqs:us-east-1:123456789012:SlackEventQueue.fifo";
+    const CHANNEL: &str = "C0123ABCD";
+    const EVENT_TS: &str = "1700000000.000100";
+
+    fn config() -> Config {
+        Config {
+            queue_arn: Some(QUEUE.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn mention(event_id: &str) -> QueueMessage {
+        QueueMessage::Event(MessageEventRequest {
+            token: "token".to_owned(),
+            api_app_id: "A0123ABCD".to_owned(),
+            r#type: "event_callback".to_owned(),
+            event_id: event_id.to_owned(),
+            event_time: 1700000000,
+            event: MessageEvent {
+                channel: CHANNEL.to_owned(),
+                r#type: "app_mention".to_owned(),
+                event_ts: EVENT_TS.to_owned(),
+                text: "<@U0BOT> how do I deploy?".to_owned(),
+                user: "U0123ABCD".to_owned(),
+                thread_ts: None,
+                channel_type: None,
+                subtype: None,
+                bot_id: None,
+            },
+        })
+    }
+
+    fn record(message_id: &str, message: &QueueMessage, receive_count: u32) -> SqsMessage {
+        SqsMessage {
+            message_id: Some(message_id.to_owned()),
+            body: Some(serde_json::to_string(message).unwrap()),
+            attributes: HashMap::from([(
+                APPROXIMATE_RECEIVE_COUNT_ATTRIBUTE.to_owned(),
+                receive_count.to_string(),
+            )]),
+            event_source_arn: Some(QUEUE.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn event(records: Vec<SqsMessage>) -> SqsEvent {
+        SqsEvent { records }
+    }
+
+    fn answer(text: &str) -> RetrievalResult {
+        RetrievalResult {
+            text: text.to_owned(),
+            citations: vec![],
+            session_id: "session-1".to_owned(),
+        }
+    }
+
+    fn posted(services: &InMemoryServices) -> Vec<ChatCall> {
+        services
+            .poster
+            .calls()
+            .into_iter()
+            .filter(|call| call.method == POST_MESSAGE_METHOD)
+            .collect()
+    }
+
+    fn failed_ids(response: &SqsBatchResponse) -> Vec<&str> {
+        response
+            .batch_item_failures
+            .iter()
+            .map(|failure| failure.item_identifier.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn answers_a_mention_in_its_thread() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        let service = services.service(config());
+
+        let response = process_event(event(vec![record("m1", &mention("Ev1"), 1)]), &service)
+            .await
+            .unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        let requests = services.retriever.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].input_query.trim(), "how do I deploy?");
+        let posted = posted(&services);
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].body["channel"], CHANNEL);
+        assert_eq!(posted[0].body["thread_ts"], EVENT_TS);
+        assert!(posted[0].body.to_string().contains("Run the deploy job."));
+        assert_eq!(
+            service
+                .session_store
+                .get(CHANNEL, EVENT_TS)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("session-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_a_duplicate_delivery_once() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        services
+            .retriever
+            .push_result(answer("Run the deploy job again."));
+        let service = services.service(config());
+        let message = mention("Ev1");
+
+        let response = process_event(
+            event(vec![record("m1", &message, 1), record("m2", &message, 1)]),
+            &service,
+        )
+        .await
+        .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+
+        // redelivered once answered.
+        let response = process_event(event(vec![record("m1", &message, 2)]), &service)
+            .await
+            .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+
+        assert_eq!(services.retriever.requests().len(), 1);
+        assert_eq!(posted(&services).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retryable_failure_is_returned_to_the_queue() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        services
+            .poster
+            .fail_next(POST_MESSAGE_METHOD, "internal_error");
+        let service = services.service(config());
+        let message = mention("Ev1");
+
+        let response = process_event(event(vec![record("m1", &message, 1)]), &service)
+            .await
+            .unwrap();
+
+        assert_eq!(failed_ids(&response), vec!["m1"]);
+        // no error reply, only the failed answer.
+        assert_eq!(posted(&services).len(), 1);
+
+        // released, so the retry answers.
+        let response = process_event(event(vec![record("m1", &message, 2)]), &service)
+            .await
+            .unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        let posted = posted(&services);
+        assert_eq!(posted.len(), 2);
+        assert!(posted[1].body.to_string().contains("Run the deploy job."));
+    }
+
+    #[tokio::test]
+    async fn retryable_failure_on_the_last_attempt_is_replied() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        services
+            .poster
+            .fail_next(POST_MESSAGE_METHOD, "internal_error");
+        let service = services.service(Config {
+            max_receive_count: Some(3),
+            ..config()
+        });
+
+        let response = process_event(event(vec![record("m1", &mention("Ev1"), 3)]), &service)
+            .await
+            .unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        let posted = posted(&services);
+        assert_eq!(posted.len(), 2);
+        let reply = posted[1].body.to_string();
+        assert!(reply.contains("Reference ID: `Ev1`"));
+        assert!(reply.contains("something went wrong"));
+    }
+
+    #[tokio::test]
+    async fn non_retryable_failure_is_replied_once() {
+        // nothing to answer with, so no relevant content.
+        let services = InMemoryServices::new();
+        let service = services.service(config());
+        let message = mention("Ev1");
+
+        let response = process_event(event(vec![record("m1", &message, 1)]), &service)
+            .await
+            .unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        let posted = posted(&services);
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].body["thread_ts"], EVENT_TS);
+        let reply = posted[0].body.to_string();
+        assert!(reply.contains("couldn't find anything"));
+        assert!(reply.contains("Reference ID: `Ev1`"));
+
+        let response = process_event(event(vec![record("m1", &message, 2)]), &service)
+            .await
+            .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+        assert_eq!(services.retriever.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unavailable_channel_is_not_replied() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        services
+            .poster
+            .fail_next(POST_MESSAGE_METHOD, "channel_not_found");
+        let service = services.service(config());
+
+        let response = process_event(event(vec![record("m1", &mention("Ev1"), 1)]), &service)
+            .await
+            .unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        // the failed answer only, no error reply.
+        assert_eq!(posted(&services).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn messages_of_another_queue_are_ignored() {
+        let services = InMemoryServices::new();
+        services
+            .retriever
+            .push_result(answer("Run the deploy job."));
+        let service = services.service(config());
+        let mut record = record("m1", &mention("Ev1"), 1);
+        record.event_source_arn =
+            Some("arn:aws:sqs:us-east-1:123456789012:OtherQueue.fifo".to_owned());
+
+        let response = process_event(event(vec![record]), &service).await.unwrap();
+
+        assert!(response.batch_item_failures.is_empty());
+        assert!(services.retriever.requests().is_empty());
+        assert!(services.poster.calls().is_empty());
+    }
+}